@@ -4,7 +4,7 @@ use minetestworld::World;
 
 fn main() {
     task::block_on(async {
-        let world = World::open("TestWorld");
+        let world = World::open("TestWorld").await.unwrap();
         let data = world.get_map_data_backend(false).await.unwrap();
         let mut positions = data.all_mapblock_positions().await;
         while let Some(pos) = positions.try_next().await.unwrap() {
@@ -3,7 +3,7 @@ use minetestworld::World;
 
 #[async_std::main]
 async fn main() {
-    let world = World::open("TestWorld");
+    let world = World::open("TestWorld").await.unwrap();
     let data = world.get_map_data().await.unwrap();
     let mut positions = data.all_mapblock_positions().await;
     while let Some(pos) = positions.try_next().await.unwrap() {
@@ -0,0 +1,66 @@
+//! Contains the [`AuthRecord`] type to read the authentication data of a world
+
+use crate::runtime as fs;
+use crate::world::WorldError;
+use crate::MapDataError;
+use log::LevelFilter;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use sqlx::{prelude::*, ConnectOptions};
+use std::path::Path;
+
+/// The authentication data of a player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthRecord {
+    /// The player's name
+    pub name: String,
+    /// The SRP verifier and salt in Minetest's encoding, or an empty string for no password
+    pub password_hash: String,
+    /// Time of the last login, in seconds since the Unix epoch
+    pub last_login: i64,
+    /// The privileges granted to the player, sorted by name
+    pub privileges: Vec<String>,
+}
+
+/// Reads all authentication records from an `auth.sqlite` database, sorted by name
+pub(crate) async fn read_auth_sqlite(filename: &Path) -> Result<Vec<AuthRecord>, WorldError> {
+    if let Err(e) = fs::metadata(filename).await {
+        // Minetest creates the database once the first player joins
+        return match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(vec![]),
+            _ => Err(e.into()),
+        };
+    }
+    let opts = SqliteConnectOptions::new()
+        .filename(filename)
+        .read_only(true)
+        .log_statements(LevelFilter::Debug);
+    let pool = SqlitePool::connect_with(opts)
+        .await
+        .map_err(MapDataError::SqlError)?;
+    read_records(&pool)
+        .await
+        .map_err(|e| MapDataError::SqlError(e).into())
+}
+
+async fn read_records(pool: &SqlitePool) -> Result<Vec<AuthRecord>, sqlx::Error> {
+    let rows = sqlx::query("SELECT id, name, password, last_login FROM auth ORDER BY name")
+        .fetch_all(pool)
+        .await?;
+    let mut records = Vec::with_capacity(rows.len());
+    for row in rows {
+        let id: i64 = row.try_get("id")?;
+        let privileges = sqlx::query_scalar(
+            "SELECT privilege FROM user_privileges WHERE id = ? ORDER BY privilege",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        records.push(AuthRecord {
+            name: row.try_get("name")?,
+            password_hash: row.try_get("password")?,
+            last_login: row.try_get("last_login")?,
+            privileges,
+        });
+    }
+    Ok(records)
+}
@@ -0,0 +1,121 @@
+//! Synchronous wrappers around the async API
+//!
+//! Each call blocks the current thread until the underlying future completes.
+//!
+//! ```
+//! use minetestworld::blocking::World;
+//! use minetestworld::Position;
+//!
+//! let world = World::open("TestWorld").unwrap();
+//! let map_data = world.get_map_data().unwrap();
+//! let block = map_data.get_mapblock(Position::new(-13i16, -8, 2)).unwrap();
+//! ```
+
+use crate::map_data::MapDataError;
+use crate::runtime::block_on;
+use crate::world::WorldError;
+use crate::{MapBlock, Node, Position};
+use futures::TryStreamExt;
+use std::path::Path;
+
+type Result<T> = std::result::Result<T, MapDataError>;
+
+/// Blocking variant of [`crate::World`]
+pub struct World(crate::World);
+
+impl World {
+    /// Opens the world located in the given directory
+    ///
+    /// See [`crate::World::open`].
+    pub fn open(path: impl AsRef<Path>) -> std::result::Result<Self, WorldError> {
+        block_on(crate::World::open(path)).map(World)
+    }
+
+    /// Returns a handle to the map database
+    ///
+    /// See [`crate::World::get_map_data`].
+    pub fn get_map_data(&self) -> std::result::Result<MapData, WorldError> {
+        block_on(self.0.get_map_data()).map(MapData)
+    }
+
+    /// Returns a VoxelManip with the ability to read and write nodes
+    ///
+    /// See [`crate::World::get_voxel_manip`].
+    pub fn get_voxel_manip(&self, writable: bool) -> std::result::Result<VoxelManip, WorldError> {
+        block_on(self.0.get_voxel_manip(writable)).map(VoxelManip)
+    }
+
+    /// Returns the async world this wraps
+    pub fn into_inner(self) -> crate::World {
+        self.0
+    }
+}
+
+/// Blocking variant of [`crate::MapData`]
+pub struct MapData(crate::MapData);
+
+impl MapData {
+    /// Returns the positions of all mapblocks
+    ///
+    /// See [`crate::MapData::all_mapblock_positions`].
+    pub fn all_mapblock_positions(&self) -> Result<Vec<Position>> {
+        block_on(async { self.0.all_mapblock_positions().await.try_collect().await })
+    }
+
+    /// Queries the backend for a specific map block
+    ///
+    /// See [`crate::MapData::get_mapblock`].
+    pub fn get_mapblock(&self, pos: Position) -> Result<MapBlock> {
+        block_on(self.0.get_mapblock(pos))
+    }
+
+    /// Inserts or replaces the map block at `pos`
+    ///
+    /// See [`crate::MapData::set_mapblock`].
+    pub fn set_mapblock(&self, pos: Position, block: &MapBlock) -> Result<()> {
+        block_on(self.0.set_mapblock(pos, block))
+    }
+
+    /// Returns the async map data handle this wraps
+    pub fn into_inner(self) -> crate::MapData {
+        self.0
+    }
+}
+
+/// Blocking variant of [`crate::VoxelManip`]
+pub struct VoxelManip(crate::VoxelManip);
+
+impl VoxelManip {
+    /// Get the node at the given world position
+    ///
+    /// See [`crate::VoxelManip::get_node`].
+    pub fn get_node(&mut self, node_pos: Position) -> Result<Node> {
+        block_on(self.0.get_node(node_pos))
+    }
+
+    /// Set a voxel in VoxelManip's cache
+    ///
+    /// See [`crate::VoxelManip::set_node`].
+    pub fn set_node(&mut self, node_pos: Position, node: Node) -> Result<()> {
+        block_on(self.0.set_node(node_pos, node))
+    }
+
+    /// Sets the content string at this world position
+    ///
+    /// See [`crate::VoxelManip::set_content`].
+    pub fn set_content(&mut self, node_pos: Position, content: &[u8]) -> Result<()> {
+        block_on(self.0.set_content(node_pos, content))
+    }
+
+    /// Apply all changes made to the map
+    ///
+    /// See [`crate::VoxelManip::commit`].
+    pub fn commit(&mut self) -> Result<()> {
+        block_on(self.0.commit())
+    }
+
+    /// Returns the async VoxelManip this wraps
+    pub fn into_inner(self) -> crate::VoxelManip {
+        self.0
+    }
+}
@@ -1,6 +1,6 @@
 //! This crate lets you read the world data of a minetest world.
 //!
-//! Only map format version 29 is supported. LevelDB backend is not supported.
+//! Only map format version 29 is supported. Support for the LevelDB backend is experimental.
 //!
 //! ## Terminology
 //! ### Node
@@ -23,8 +23,17 @@
 //!
 //! This code snippet that reads all nodes of a specific map block:
 //! ```
+//! # #[cfg(feature = "sqlite")]
+//! # fn main() {
 //! use minetestworld::{World, Position};
+//! # #[cfg(feature = "async-std")]
 //! use async_std::task;
+//! # #[cfg(feature = "tokio")]
+//! # mod task {
+//! #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+//! #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+//! #     }
+//! # }
 //!
 //! let blockpos = Position {
 //!     x: -13,
@@ -33,12 +42,15 @@
 //! };
 //!
 //! task::block_on(async {
-//!     let world = World::open("TestWorld");
+//!     let world = World::open("TestWorld").await.unwrap();
 //!     let mapdata = world.get_map_data().await.unwrap();
 //!     for (pos, node) in mapdata.iter_mapblock_nodes(blockpos).await.unwrap() {
 //!         println!("{pos:?}, {node:?}");
 //!     }
 //! });
+//! # }
+//! # #[cfg(not(feature = "sqlite"))]
+//! # fn main() {}
 //! ```
 //!
 //! [Another notable example](https://docs.rs/crate/minetestworld/latest/source/examples/modify_map.rs)
@@ -47,27 +59,60 @@
 #![forbid(unsafe_code)]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+#[cfg(feature = "async-std")]
 extern crate async_std;
 #[cfg(feature = "smartstring")]
 extern crate smartstring;
 
+/// Only declares the items if at least one map data backend is enabled
+///
+/// Without a backend, the crate is limited to working with map blocks and positions.
+macro_rules! with_backend {
+    ($($item:item)*) => {
+        $(
+            #[cfg(any(
+                feature = "sqlite",
+                feature = "postgres",
+                feature = "redis",
+                feature = "experimental-leveldb"
+            ))]
+            $item
+        )*
+    };
+}
+
+#[cfg(feature = "sqlite")]
+pub mod auth;
 pub mod map_block;
-pub mod map_data;
 pub mod positions;
-pub mod voxel_manip;
-pub mod world;
+with_backend! {
+    #[cfg(feature = "blocking")]
+    pub mod blocking;
+    pub mod map_data;
+    pub mod players;
+    mod runtime;
+    pub mod voxel_manip;
+    pub mod world;
+}
 
+#[cfg(feature = "sqlite")]
+pub use auth::AuthRecord;
 pub use map_block::MapBlock;
 pub use map_block::Node;
-pub use map_data::MapData;
-pub use map_data::MapDataError;
 pub use positions::Position;
-pub use voxel_manip::VoxelManip;
-pub use world::World;
-pub use world::WorldError as Error;
+with_backend! {
+    pub use map_data::MapData;
+    pub use map_data::MapDataError;
+    pub use players::Player;
+    pub use voxel_manip::VoxelManip;
+    pub use world::World;
+    pub use world::WorldError as Error;
+}
 
 pub use map_block::MAPBLOCK_LENGTH;
 pub use map_block::MAPBLOCK_SIZE;
 
-#[cfg(test)]
-mod tests;
+with_backend! {
+    #[cfg(test)]
+    mod tests;
+}
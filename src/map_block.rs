@@ -3,8 +3,10 @@
 use crate::positions::Position;
 
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{BufReader, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "smartstring")]
 type String = smartstring::SmartString<smartstring::LazyCompact>;
@@ -35,12 +37,92 @@ pub const MAPBLOCK_LENGTH: u8 = 16;
 pub const MAPBLOCK_SIZE: usize =
     MAPBLOCK_LENGTH as usize * MAPBLOCK_LENGTH as usize * MAPBLOCK_LENGTH as usize;
 
+/// Number of bytes per node used for the content ID (param0), see [`MapBlock::content_width`]
+///
+/// This is the only width that the supported map format version uses.
+pub const CONTENT_WIDTH: u8 = 2;
+
+/// Number of additional node params (param1 and param2), see [`MapBlock::params_width`]
+pub const PARAMS_WIDTH: u8 = 2;
+
 /// This content type string refers to an unknown content type
 pub const CONTENT_UNKNOWN: &[u8] = b"unknown";
 
 /// This content type string refers to a node that has not yet been generated
 pub const CONTENT_IGNORE: &[u8] = b"ignore";
 
+/// This content type string refers to empty space
+pub const CONTENT_AIR: &[u8] = b"air";
+
+/// Splits an [itemstring](https://wiki.minetest.net/Itemstrings) into mod name and item name
+///
+/// Unqualified names like `air` have no mod name. A leading colon, as used
+/// for overriding registrations, is ignored.
+///
+/// ```
+/// use minetestworld::map_block::split_itemstring;
+///
+/// assert_eq!(split_itemstring(b"default:stone"), (Some(&b"default"[..]), &b"stone"[..]));
+/// assert_eq!(split_itemstring(b"air"), (None, &b"air"[..]));
+/// ```
+pub fn split_itemstring(content: &[u8]) -> (Option<&[u8]>, &[u8]) {
+    match content.iter().position(|&b| b == b':') {
+        Some(0) => (None, &content[1..]),
+        Some(i) => (Some(&content[..i]), &content[i + 1..]),
+        None => (None, content),
+    }
+}
+
+/// Returns the rotation of a node whose param2 is a `facedir` value
+///
+/// The columns of the matrix are the directions that the node's x, y, and z axes point to,
+/// so `rotation[row][column]`. For example, `facedir` 0 results in the identity matrix.
+///
+/// As in Minetest, `facedir / 4` selects the direction the node's top points to
+/// (y+, z+, z-, x+, x-, y-), and `facedir % 4` the rotation around that direction.
+/// The upper three bits are ignored, since `colorfacedir` uses them for the color.
+/// The remaining values from 24 to 31 are invalid and treated as 0.
+///
+/// ```
+/// use minetestworld::map_block::facedir_rotation;
+///
+/// // Rotated by 90° around y: The node's back (z+) points to x+
+/// assert_eq!(facedir_rotation(1), [[0, 0, 1], [0, 1, 0], [-1, 0, 0]]);
+/// ```
+pub fn facedir_rotation(facedir: u8) -> [[i8; 3]; 3] {
+    const DIRS: [[i8; 3]; 6] = [
+        [0, 1, 0],
+        [0, 0, 1],
+        [0, 0, -1],
+        [1, 0, 0],
+        [-1, 0, 0],
+        [0, -1, 0],
+    ];
+    // Where the node's back (z+) points to, as index into DIRS,
+    // following Minetest's `facedir_to_dir`
+    const BACK: [usize; 24] = [
+        1, 3, 2, 4, // top y+
+        5, 3, 0, 4, // top z+
+        0, 3, 5, 4, // top z-
+        1, 5, 2, 0, // top x+
+        1, 0, 2, 5, // top x-
+        1, 4, 2, 3, // top y-
+    ];
+    let facedir = match usize::from(facedir & 0x1f) {
+        facedir @ 0..=23 => facedir,
+        _ => 0,
+    };
+    let y = DIRS[facedir / 4];
+    let z = DIRS[BACK[facedir]];
+    // x = y × z, since the rotation keeps the axes right-angled and in the same order
+    let x = [
+        y[1] * z[2] - y[2] * z[1],
+        y[2] * z[0] - y[0] * z[2],
+        y[0] * z[1] - y[1] * z[0],
+    ];
+    [[x[0], y[0], z[0]], [x[1], y[1], z[1]], [x[2], y[2], z[2]]]
+}
+
 fn read_u8(r: &mut impl Read) -> std::io::Result<u8> {
     let mut buf = [0; 1];
     r.read_exact(&mut buf)?;
@@ -65,11 +147,32 @@ fn read_i32_be(r: &mut impl Read) -> std::io::Result<i32> {
     Ok(i32::from_be_bytes(buffer))
 }
 
+/// Upper bound for preallocating vectors from untrusted element counts
+const MAX_PREALLOCATION: usize = 256;
+
+/// Reads `len` bytes without trusting `len` for the allocation size
+///
+/// A length field of a corrupt block can claim gigabytes of data.
+/// Instead of allocating all of it upfront, the buffer grows with the data actually read.
+fn read_bytes(r: &mut impl Read, len: usize, what: &str) -> Result<Vec<u8>, MapBlockError> {
+    let mut buffer = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+    r.take(len as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != len {
+        return Err(MapBlockError::BlobMalformed(format!(
+            "{what} is declared to have {len} bytes, but only {} are left",
+            buffer.len()
+        )));
+    }
+    Ok(buffer)
+}
+
 fn read_param0(r: &mut impl Read) -> std::io::Result<[u16; MAPBLOCK_SIZE]> {
-    let mut array = [0; MAPBLOCK_SIZE];
+    let mut bytes = [0; 2 * MAPBLOCK_SIZE];
+    r.read_exact(&mut bytes)?;
 
-    for p0 in array.iter_mut() {
-        *p0 = read_u16_be(r)?;
+    let mut array = [0; MAPBLOCK_SIZE];
+    for (p0, bytes) in array.iter_mut().zip(bytes.chunks_exact(2)) {
+        *p0 = u16::from_be_bytes([bytes[0], bytes[1]]);
     }
 
     Ok(array)
@@ -84,7 +187,7 @@ fn read_nodeparams(r: &mut impl Read) -> std::io::Result<[u8; MAPBLOCK_SIZE]> {
 /// The physical composition of the world at a specific voxel
 ///
 /// Nodes are the voxel-shaped 1 m³ blocks that the world consists of.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Node {
     /// Content type string
     ///
@@ -104,6 +207,126 @@ pub struct Node {
     pub param2: u8,
 }
 
+impl Node {
+    /// Returns the content type string if it is valid UTF-8
+    ///
+    /// ```
+    /// use minetestworld::Node;
+    ///
+    /// let diamond = Node {
+    ///     param0: b"default:diamond".to_vec(),
+    ///     param1: 0,
+    ///     param2: 0,
+    /// };
+    /// assert_eq!(diamond.content_str(), Some("default:diamond"));
+    /// assert_eq!(diamond.content_str_lossy(), "default:diamond");
+    /// ```
+    pub fn content_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.param0).ok()
+    }
+
+    /// Returns the content type string, replacing invalid UTF-8 sequences
+    pub fn content_str_lossy(&self) -> std::borrow::Cow<'_, str> {
+        std::string::String::from_utf8_lossy(&self.param0)
+    }
+
+    /// Whether this node consists of [air](`CONTENT_AIR`)
+    pub fn is_air(&self) -> bool {
+        self.param0 == CONTENT_AIR
+    }
+
+    /// Whether this node has not yet been [generated](`CONTENT_IGNORE`)
+    pub fn is_ignore(&self) -> bool {
+        self.param0 == CONTENT_IGNORE
+    }
+
+    /// Whether the content type of this node is [unknown](`CONTENT_UNKNOWN`)
+    pub fn is_unknown(&self) -> bool {
+        self.param0 == CONTENT_UNKNOWN
+    }
+}
+
+/// A [`Node`] whose content type string is borrowed from a map block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeRef<'a> {
+    /// Content type string, see [`Node::param0`]
+    pub param0: &'a [u8],
+    /// Lighting data
+    pub param1: u8,
+    /// Additional data
+    pub param2: u8,
+}
+
+impl NodeRef<'_> {
+    /// Copies the referenced data into an owned [`Node`]
+    pub fn to_node(&self) -> Node {
+        Node {
+            param0: self.param0.to_vec(),
+            param1: self.param1,
+            param2: self.param2,
+        }
+    }
+}
+
+/// Deduplicates content type strings across many nodes
+///
+/// Every distinct content type is stored once and shared via [`Arc`].
+/// Use it with [`MapBlock::iter_nodes_interned`] to collect the nodes of many blocks
+/// without storing the same content type string for each node.
+#[derive(Debug, Default)]
+pub struct ContentInterner {
+    contents: HashSet<Arc<[u8]>>,
+}
+
+impl ContentInterner {
+    /// Creates an empty interner
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared copy of `content`, creating it on first use
+    pub fn intern(&mut self, content: &[u8]) -> Arc<[u8]> {
+        if let Some(interned) = self.contents.get(content) {
+            return interned.clone();
+        }
+        let interned: Arc<[u8]> = Arc::from(content);
+        self.contents.insert(interned.clone());
+        interned
+    }
+
+    /// The number of distinct content types interned so far
+    pub fn len(&self) -> usize {
+        self.contents.len()
+    }
+
+    /// Whether no content type has been interned yet
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+}
+
+/// A [`Node`] whose content type string is shared through a [`ContentInterner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInterned {
+    /// Content type string, see [`Node::param0`]
+    pub param0: Arc<[u8]>,
+    /// Lighting data
+    pub param1: u8,
+    /// Additional data
+    pub param2: u8,
+}
+
+impl NodeInterned {
+    /// Copies the node into an owned [`Node`]
+    pub fn to_node(&self) -> Node {
+        Node {
+            param0: self.param0.to_vec(),
+            param1: self.param1,
+            param2: self.param2,
+        }
+    }
+}
+
 /// An error during the [decoding](`MapBlock::from_data`) of a MapBlock
 #[derive(thiserror::Error, Debug)]
 pub enum MapBlockError {
@@ -128,6 +351,13 @@ pub enum MapBlockError {
     UnsupportedNodeMetadataVersion(u8),
 }
 
+/// A mapblock-relative node position lies outside of the map block
+///
+/// Each component has to be in `0..`[`MAPBLOCK_LENGTH`].
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Relative node position {0:?} is outside of the map block")]
+pub struct OutOfBlockError(pub Position);
+
 /// Maps mapblock-local content IDs to content types
 pub type NameIdMappings = HashMap<u16, Vec<u8>>;
 
@@ -183,6 +413,119 @@ pub struct NodeTimer {
     pub elapsed: i32,
 }
 
+/// Converts milliseconds to a duration, with negative values becoming zero
+fn millis_to_duration(millis: i32) -> Duration {
+    Duration::from_millis(millis.max(0) as u64)
+}
+
+/// Converts a duration to milliseconds, clamping it to the range of `i32`
+fn duration_to_millis(duration: Duration) -> i32 {
+    duration.as_millis().min(i32::MAX as u128) as i32
+}
+
+impl NodeTimer {
+    /// Returns the timeout as a [`Duration`]
+    ///
+    /// A negative timeout is returned as zero.
+    pub fn timeout_duration(&self) -> Duration {
+        millis_to_duration(self.timeout)
+    }
+
+    /// Returns the elapsed time as a [`Duration`]
+    ///
+    /// A negative elapsed time is returned as zero.
+    pub fn elapsed_duration(&self) -> Duration {
+        millis_to_duration(self.elapsed)
+    }
+
+    /// Sets the timeout, clamped to `i32::MAX` milliseconds
+    ///
+    /// ```
+    /// use minetestworld::map_block::NodeTimer;
+    /// use minetestworld::Position;
+    /// use std::time::Duration;
+    ///
+    /// let mut timer = NodeTimer { position: Position::ORIGIN, timeout: 0, elapsed: 0 };
+    /// timer.set_timeout_duration(Duration::from_secs(40));
+    /// assert_eq!(timer.timeout, 40_000);
+    /// ```
+    pub fn set_timeout_duration(&mut self, timeout: Duration) {
+        self.timeout = duration_to_millis(timeout);
+    }
+
+    /// Sets the elapsed time, clamped to `i32::MAX` milliseconds
+    pub fn set_elapsed_duration(&mut self, elapsed: Duration) {
+        self.elapsed = duration_to_millis(elapsed);
+    }
+}
+
+/// The leading fields of a map block, which can be read without decoding the whole block
+///
+/// See [`MapBlock::read_header`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapBlockHeader {
+    /// The format version of the mapblock
+    pub map_format_version: u8,
+    /// Flags telling if this chunk is underground etc.
+    pub flags: u8,
+    /// Flags that indicate if the lighting is complete at each side
+    pub lighting_complete: u16,
+    /// Timestamp of last save, in seconds from game start
+    pub timestamp: u32,
+}
+
+/// An overview of a map block's contents, e.g. for debugging output
+///
+/// See [`MapBlock::summary`]. Its [`Display`](std::fmt::Display) implementation
+/// renders it as a single line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSummary {
+    /// Number of distinct content types of the nodes
+    pub distinct_contents: usize,
+    /// Each content type with the number of nodes it occurs in, most frequent first
+    pub content_counts: Vec<(Vec<u8>, u32)>,
+    /// Number of node metadata entries
+    pub metadata: usize,
+    /// Number of node timers
+    pub timers: usize,
+    /// Number of static objects
+    pub objects: usize,
+    /// Timestamp of last save, in seconds from game start
+    pub timestamp: u32,
+    /// Whether the block is underground
+    pub underground: bool,
+}
+
+impl std::fmt::Display for BlockSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} contents (", self.distinct_contents)?;
+        for (i, (content, count)) in self.content_counts.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(
+                f,
+                "{}: {count}",
+                std::string::String::from_utf8_lossy(content)
+            )?;
+        }
+        write!(
+            f,
+            "), {} metadata, {} timers, {} objects, timestamp {}",
+            self.metadata, self.timers, self.objects, self.timestamp
+        )?;
+        if self.underground {
+            write!(f, ", underground")?;
+        }
+        Ok(())
+    }
+}
+
+/// Bit in [`MapBlock::flags`] that is set if the block is underground
+const FLAG_IS_UNDERGROUND: u8 = 0x01;
+/// Bit in [`MapBlock::flags`] that is set if the lighting differs between day and night
+const FLAG_DAY_NIGHT_DIFFERS: u8 = 0x02;
+
 /// A 'chunk' of voxels; the data unit saved in a backend
 ///
 /// Refer to <https://github.com/minetest/minetest/blob/master/doc/world_format.md>
@@ -194,20 +537,27 @@ pub struct MapBlock {
     /// [`MapBlockError::MapVersionError`].
     pub map_format_version: u8,
     /// Flags telling if this chunk is underground etc.
+    ///
+    /// See [`MapBlock::is_underground`] and [`MapBlock::day_night_differs`].
     pub flags: u8,
     /// Flags that indicate if the lighting is complete at each side
+    ///
+    /// Bits 0 to 5 are for the day bank, bits 6 to 11 for the night bank,
+    /// each in the face order X+, Y+, Z+, Z-, Y-, X-. The upper four bits are unused.
+    /// A cleared bit makes the client recalculate the light at that face.
+    /// See [`MapBlock::set_lighting_complete_all`].
     pub lighting_complete: u16,
     /// Timestamp of last save, in seconds from game start
     pub timestamp: u32,
     /// Maps all numeric content IDs to content itemstrings.
     ///
-    /// This is used to efficiently store node contents in the [`MapBlock::param0`] field: 
+    /// This is used to efficiently store node contents in the [`MapBlock::param0`] field:
     /// Each content itemstring has to be stored only one time per block,
     /// instead of one time per node.
     pub name_id_mappings: NameIdMappings,
-    /// Number bytes used for the content (param0) field of the nodes
+    /// Number bytes used for the content (param0) field of the nodes, always [`CONTENT_WIDTH`]
     pub content_width: u8,
-    /// Additional node params, always 2
+    /// Additional node params, always [`PARAMS_WIDTH`]
     pub params_width: u8,
     /// The content ID of each node in the mapblock.
     ///
@@ -227,15 +577,56 @@ pub struct MapBlock {
 
 impl MapBlock {
     /// Constructs a Mapblock from its binary representation
-    pub fn from_data(mut data: impl Read) -> Result<MapBlock, MapBlockError> {
+    pub fn from_data(data: impl Read) -> Result<MapBlock, MapBlockError> {
+        Self::from_data_with_dict(data, &[])
+    }
+
+    /// Constructs a Mapblock from a binary representation that was compressed with a zstd dictionary
+    ///
+    /// Minetest itself does not use dictionaries; this is meant for custom servers that do.
+    /// An empty `dict` means no dictionary, like in [`MapBlock::from_data`].
+    pub fn from_data_with_dict(
+        mut data: impl Read,
+        dict: &[u8],
+    ) -> Result<MapBlock, MapBlockError> {
         let map_format_version = read_u8(&mut data)?;
         if map_format_version != 29 {
             return Err(MapBlockError::MapVersionError(map_format_version));
         }
-        // Read all into a vector
-        let mut buffer = vec![];
-        zstd::stream::Decoder::new(data)?.read_to_end(&mut buffer)?;
-        let mut data = buffer.as_slice();
+        Self::decode(
+            map_format_version,
+            zstd::stream::Decoder::with_dictionary(BufReader::new(data), dict)?,
+        )
+    }
+
+    /// Constructs a Mapblock from its binary representation in memory
+    ///
+    /// This is equivalent to [`MapBlock::from_data`], but decompresses directly from the slice.
+    ///
+    /// ```
+    /// use minetestworld::{MapBlock, Position};
+    ///
+    /// let data = MapBlock::unloaded().to_binary().unwrap();
+    /// let block = MapBlock::from_bytes(&data).unwrap();
+    /// assert!(block.get_node_at(Position::ORIGIN).is_ignore());
+    /// ```
+    pub fn from_bytes(data: &[u8]) -> Result<MapBlock, MapBlockError> {
+        let Some((&map_format_version, compressed)) = data.split_first() else {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        };
+        if map_format_version != 29 {
+            return Err(MapBlockError::MapVersionError(map_format_version));
+        }
+        Self::decode(
+            map_format_version,
+            zstd::stream::Decoder::with_buffer(compressed)?,
+        )
+    }
+
+    /// Parses the block from the decompressed data following the version byte
+    fn decode(map_format_version: u8, data: impl Read) -> Result<MapBlock, MapBlockError> {
+        // Parse while decompressing, so that no buffer for the whole block is needed
+        let mut data = BufReader::new(data);
 
         let flags = read_u8(&mut data)?;
         let lighting_complete = read_u16_be(&mut data)?;
@@ -243,14 +634,14 @@ impl MapBlock {
         let name_id_mappings = read_name_id_mappings(&mut data)?;
 
         let content_width = read_u8(&mut data)?;
-        if content_width != 2 {
+        if content_width != CONTENT_WIDTH {
             return Err(MapBlockError::BlobMalformed(format!(
                 "\"{content_width}\" is not the expected content_width"
             )));
         }
 
         let params_width = read_u8(&mut data)?;
-        if params_width != 2 {
+        if params_width != PARAMS_WIDTH {
             return Err(MapBlockError::BlobMalformed(format!(
                 "\"{params_width}\" is not the expected params_width"
             )));
@@ -272,20 +663,118 @@ impl MapBlock {
             node_timers: read_timers(&mut data)?,
         };
 
+        let trailing_bytes = std::io::copy(&mut data, &mut std::io::sink())?;
+        if trailing_bytes > 0 {
+            return Err(MapBlockError::BlobMalformed(format!(
+                "{trailing_bytes} unexpected bytes after the node timers"
+            )));
+        }
+
         Ok(mapblock)
     }
 
+    /// Returns the map format version of a serialized map block
+    ///
+    /// Only the first byte is read, so this works for every version,
+    /// including the ones [`MapBlock::from_data`] does not support.
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// assert_eq!(MapBlock::peek_version(&[25, 0, 0]).unwrap(), 25);
+    /// assert!(MapBlock::peek_version(&[]).is_err());
+    /// ```
+    pub fn peek_version(data: &[u8]) -> std::io::Result<u8> {
+        read_u8(&mut &data[..])
+    }
+
+    /// Reads only the header of a map block from its binary representation
+    ///
+    /// Decompression stops right after the timestamp,
+    /// which makes this much cheaper than [`MapBlock::from_data`].
+    pub fn read_header(mut data: impl Read) -> Result<MapBlockHeader, MapBlockError> {
+        let map_format_version = read_u8(&mut data)?;
+        if map_format_version != 29 {
+            return Err(MapBlockError::MapVersionError(map_format_version));
+        }
+        let mut data = zstd::stream::Decoder::new(data)?;
+        Ok(MapBlockHeader {
+            map_format_version,
+            flags: read_u8(&mut data)?,
+            lighting_complete: read_u16_be(&mut data)?,
+            timestamp: read_u32_be(&mut data)?,
+        })
+    }
+
     /// Serializes the map block into the binary format
+    ///
+    /// This uses zstd's default compression level.
+    /// See [`MapBlock::to_binary_with_level`] to choose another one.
     pub fn to_binary(&self) -> std::io::Result<Vec<u8>> {
-        let mut encoder = zstd::stream::Encoder::new(vec![29], 0)?;
+        self.to_binary_with_level(zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    /// Serializes the map block into the binary format, using the given zstd compression level
+    ///
+    /// Valid levels are in [`zstd::compression_level_range`], which is usually `1..=22`
+    /// plus negative levels for faster, weaker compression. `0` selects zstd's default level.
+    /// Higher levels result in smaller blocks, but take longer to encode.
+    ///
+    /// Minetest decodes any valid zstd stream, so the level does not affect compatibility.
+    ///
+    /// Fails with [`std::io::ErrorKind::InvalidInput`] if the block holds more entries
+    /// than the format can represent, e.g. more than 65535 name-id mappings,
+    /// or if [`MapBlock::content_width`] or [`MapBlock::params_width`] differ from
+    /// [`CONTENT_WIDTH`] and [`PARAMS_WIDTH`].
+    pub fn to_binary_with_level(&self, level: i32) -> std::io::Result<Vec<u8>> {
+        self.encode(level, &[])
+    }
+
+    /// Serializes the map block into the binary format, compressing it with a zstd dictionary
+    ///
+    /// The result can only be decoded with [`MapBlock::from_data_with_dict`] and the same
+    /// dictionary. Minetest itself does not support this.
+    pub fn to_binary_with_dict(&self, dict: &[u8]) -> std::io::Result<Vec<u8>> {
+        self.encode(zstd::DEFAULT_COMPRESSION_LEVEL, dict)
+    }
+
+    /// Serializes the map block and decodes the result again
+    ///
+    /// This lets crates that build their own blocks check that they serialize stably,
+    /// like the map blocks read from a world do.
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// let block = MapBlock::unloaded();
+    /// let decoded = block.roundtrip().unwrap();
+    /// assert_eq!(decoded.param0, block.param0);
+    /// assert_eq!(decoded.name_id_mappings, block.name_id_mappings);
+    /// ```
+    pub fn roundtrip(&self) -> Result<MapBlock, MapBlockError> {
+        MapBlock::from_bytes(&self.to_binary()?)
+    }
+
+    fn encode(&self, level: i32, dict: &[u8]) -> std::io::Result<Vec<u8>> {
+        // The node arrays are always written with these widths
+        if (self.content_width, self.params_width) != (CONTENT_WIDTH, PARAMS_WIDTH) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "content_width {} and params_width {} are not supported",
+                    self.content_width, self.params_width
+                ),
+            ));
+        }
+
+        let mut encoder = zstd::stream::Encoder::with_dictionary(vec![29], level, dict)?;
 
         encoder.write_all(&self.flags.to_be_bytes())?;
         encoder.write_all(&self.lighting_complete.to_be_bytes())?;
         encoder.write_all(&self.timestamp.to_be_bytes())?;
         write_name_id_mappings(&self.name_id_mappings, &mut encoder)?;
 
-        encoder.write_all(&[2])?; // content_width
-        encoder.write_all(&[2])?; // params_width
+        encoder.write_all(&[CONTENT_WIDTH, PARAMS_WIDTH])?;
 
         for value in self.param0 {
             encoder.write_all(&value.to_be_bytes())?;
@@ -300,8 +789,52 @@ impl MapBlock {
         encoder.finish()
     }
 
+    /// Computes the length of the serialized block before compression
+    ///
+    /// This is the byte count of the version byte and the data that [`MapBlock::to_binary`]
+    /// passes to zstd. The compressed size is usually much smaller.
+    pub fn estimate_serialized_len(&self) -> usize {
+        let name_id_mappings: usize = self
+            .name_id_mappings
+            .values()
+            .map(|name| 2 + 2 + name.len())
+            .sum();
+        let node_metadata: usize = match self.node_metadata.len() {
+            0 => 1,
+            _ => {
+                1 + 2
+                    + self
+                        .node_metadata
+                        .iter()
+                        .map(|metadatum| {
+                            let vars: usize = metadatum
+                                .vars
+                                .iter()
+                                .map(|var| 2 + var.key.len() + 4 + var.value.len() + 1)
+                                .sum();
+                            2 + 4 + vars + metadatum.inventory.len()
+                        })
+                        .sum::<usize>()
+            }
+        };
+        let static_objects: usize = self
+            .static_objects
+            .iter()
+            .map(|object| 1 + 12 + 2 + object.data.len())
+            .sum();
+
+        1 // map format version
+            + 1 + 2 + 4 // flags, lighting_complete, timestamp
+            + 1 + 2 + name_id_mappings
+            + 1 + 1 // content_width, params_width
+            + MAPBLOCK_SIZE * (2 + 1 + 1) // param0, param1, param2
+            + node_metadata
+            + 1 + 2 + static_objects
+            + 1 + 2 + self.node_timers.len() * 10
+    }
+
     /// Creates a map block that contains only [`CONTENT_IGNORE`] nodes
-    /// 
+    ///
     /// It represents a block that was not yet generated by the world generator.
     pub fn unloaded() -> Self {
         MapBlock {
@@ -310,8 +843,8 @@ impl MapBlock {
             lighting_complete: 0,
             timestamp: 0xffffffff,
             name_id_mappings: HashMap::from([(0, Vec::from(CONTENT_IGNORE))]),
-            content_width: 2,
-            params_width: 2,
+            content_width: CONTENT_WIDTH,
+            params_width: PARAMS_WIDTH,
             param0: [0; MAPBLOCK_SIZE],
             param1: [0; MAPBLOCK_SIZE],
             param2: [0; MAPBLOCK_SIZE],
@@ -321,27 +854,181 @@ impl MapBlock {
         }
     }
 
+    /// Creates a map block in which every node is `node`
+    ///
+    /// Unlike [`MapBlock::unloaded`], the block counts as generated:
+    /// Its timestamp is zero and lighting is marked as complete on all sides.
+    pub fn new_filled(node: &Node) -> Self {
+        let mut mapblock = MapBlock {
+            timestamp: 0,
+            lighting_complete: 0xffff,
+            ..MapBlock::unloaded()
+        };
+        mapblock.fill(node);
+        mapblock
+    }
+
+    /// Replaces every node of this block with `node`
+    ///
+    /// The name-id mappings are reset to contain only the content of `node`.
+    /// Node metadata, node timers, and static objects are removed,
+    /// since they belong to the nodes that are replaced.
+    pub fn fill(&mut self, node: &Node) {
+        self.name_id_mappings = HashMap::from([(0, node.param0.clone())]);
+        self.param0 = [0; MAPBLOCK_SIZE];
+        self.param1 = [node.param1; MAPBLOCK_SIZE];
+        self.param2 = [node.param2; MAPBLOCK_SIZE];
+        self.node_metadata.clear();
+        self.node_timers.clear();
+        self.static_objects.clear();
+    }
+
+    /// Whether the block is underground, i.e. not exposed to sunlight
+    ///
+    /// This is bit 0 (`0x01`) of [`MapBlock::flags`].
+    pub fn is_underground(&self) -> bool {
+        self.flags & FLAG_IS_UNDERGROUND != 0
+    }
+
+    /// Sets or clears the underground bit of [`MapBlock::flags`]
+    pub fn set_underground(&mut self, underground: bool) {
+        self.set_flag(FLAG_IS_UNDERGROUND, underground);
+    }
+
+    /// Whether the lighting of the block differs between day and night
+    ///
+    /// This is bit 1 (`0x02`) of [`MapBlock::flags`].
+    pub fn day_night_differs(&self) -> bool {
+        self.flags & FLAG_DAY_NIGHT_DIFFERS != 0
+    }
+
+    /// Sets or clears the day-night-differs bit of [`MapBlock::flags`]
+    pub fn set_day_night_differs(&mut self, differs: bool) {
+        self.set_flag(FLAG_DAY_NIGHT_DIFFERS, differs);
+    }
+
+    /// Marks the lighting of all faces as complete or incomplete
+    ///
+    /// This sets or clears all bits of [`MapBlock::lighting_complete`].
+    /// Incomplete lighting makes the client recalculate the light of the block,
+    /// which is visible as flicker, so generators can mark blocks they lit themselves as complete.
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// let mut block = MapBlock::unloaded();
+    /// block.set_lighting_complete_all(false);
+    /// assert_eq!(block.lighting_complete, 0);
+    /// ```
+    pub fn set_lighting_complete_all(&mut self, complete: bool) {
+        self.lighting_complete = if complete { 0xffff } else { 0 };
+    }
+
+    fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
     /// Gets the content type string from a content ID
     ///
     /// If the ID is not present, [`CONTENT_UNKNOWN`] is returned.
     pub fn content_from_id(&self, content_id: u16) -> &[u8] {
-        self.name_id_mappings
-            .get(&content_id)
-            .map(|v| v.as_slice())
+        self.content_from_id_opt(content_id)
             .unwrap_or(CONTENT_UNKNOWN)
     }
 
+    /// Gets the content type string from a content ID, if the ID is present
+    ///
+    /// Unlike [`MapBlock::content_from_id`], this tells a node of the `unknown` type
+    /// apart from an ID that is missing in the name-id mapping, which indicates corruption.
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// let block = MapBlock::unloaded();
+    /// assert_eq!(block.content_from_id_opt(0), Some(&b"ignore"[..]));
+    /// assert_eq!(block.content_from_id_opt(1), None);
+    /// ```
+    pub fn content_from_id_opt(&self, content_id: u16) -> Option<&[u8]> {
+        self.name_id_mappings.get(&content_id).map(|v| v.as_slice())
+    }
+
     /// Queries the mapblock for a node on the given mapblock-relative coordinates
     pub fn get_node_at(&self, relative_node_pos: Position) -> Node {
+        self.get_node_ref_at(relative_node_pos).to_node()
+    }
+
+    /// Like [`MapBlock::get_node_at`], but borrows the content type string from this block
+    pub fn get_node_ref_at(&self, relative_node_pos: Position) -> NodeRef<'_> {
         let index = relative_node_pos.as_node_index() as usize % MAPBLOCK_SIZE;
-        let param0 = self.content_from_id(self.param0[index]);
-        Node {
-            param0: param0.to_vec(),
+        NodeRef {
+            param0: self.content_from_id(self.param0[index]),
             param1: self.param1[index],
             param2: self.param2[index],
         }
     }
 
+    /// Iterates over the nodes in the cuboid from `min` to `max`, both included
+    ///
+    /// The corners are mapblock-relative node positions. The region is clamped to the map block,
+    /// so only nodes inside of it are yielded, with x varying fastest.
+    /// If a component of `min` is greater than the one of `max`, the region is empty.
+    ///
+    /// ```
+    /// use minetestworld::{MapBlock, Position};
+    ///
+    /// let block = MapBlock::unloaded();
+    /// // Skip the outer shell of the block
+    /// let interior = block.iter_region(Position::splat(1), Position::splat(14));
+    /// assert_eq!(interior.count(), 14 * 14 * 14);
+    /// ```
+    pub fn iter_region(
+        &self,
+        min: Position,
+        max: Position,
+    ) -> impl Iterator<Item = (Position, NodeRef<'_>)> + '_ {
+        let last = MAPBLOCK_LENGTH as i16 - 1;
+        let min = Position::new(min.x.max(0), min.y.max(0), min.z.max(0));
+        let max = Position::new(max.x.min(last), max.y.min(last), max.z.min(last));
+        (min.z..=max.z).flat_map(move |z| {
+            (min.y..=max.y).flat_map(move |y| {
+                (min.x..=max.x).map(move |x| {
+                    let pos = Position::new(x, y, z);
+                    (pos, self.get_node_ref_at(pos))
+                })
+            })
+        })
+    }
+
+    /// Iterates over all nodes of the block, sharing their content type strings via `interner`
+    ///
+    /// The nodes are yielded along with their mapblock-relative position, in node index order.
+    /// Nodes of the same content type share one allocation, also across blocks
+    /// that are iterated with the same interner.
+    pub fn iter_nodes_interned(
+        &self,
+        interner: &mut ContentInterner,
+    ) -> impl Iterator<Item = (Position, NodeInterned)> + '_ {
+        let contents: HashMap<u16, Arc<[u8]>> = self
+            .name_id_mappings
+            .iter()
+            .map(|(&id, content)| (id, interner.intern(content)))
+            .collect();
+        let unknown = interner.intern(CONTENT_UNKNOWN);
+        (0..MAPBLOCK_SIZE).map(move |index| {
+            let param0 = contents.get(&self.param0[index]).unwrap_or(&unknown);
+            let node = NodeInterned {
+                param0: param0.clone(),
+                param1: self.param1[index],
+                param2: self.param2[index],
+            };
+            (Position::from_node_index(index as u16), node)
+        })
+    }
+
     /// Gather the content ID associated with this content name, if present
     pub fn get_content_id(&self, content: &[u8]) -> Option<u16> {
         self.name_id_mappings
@@ -350,9 +1037,36 @@ impl MapBlock {
             .map(|(&k, _v)| k)
     }
 
+    /// The number of content IDs in use, i.e. the number of name-id mappings
+    pub fn content_id_count(&self) -> usize {
+        self.name_id_mappings.len()
+    }
+
+    /// The content IDs that occur in the nodes of this block, in ascending order
+    ///
+    /// Unlike the name-id mappings, this does not include IDs that no node uses anymore.
+    pub fn content_ids_used(&self) -> BTreeSet<u16> {
+        self.param0.iter().copied().collect()
+    }
+
+    /// How many more content IDs can be added to this block
+    ///
+    /// The map format stores the number of name-id mappings as 16 bit integer,
+    /// so a block holds at most 65535 content IDs.
+    /// Adding a content when there is no headroom left panics.
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// assert_eq!(MapBlock::unloaded().content_id_headroom(), 65534);
+    /// ```
+    pub fn content_id_headroom(&self) -> usize {
+        usize::from(u16::MAX).saturating_sub(self.content_id_count())
+    }
+
     /// Add a new content string, returning a new content ID
     ///
-    /// Panics if there are already ~65k content IDs present
+    /// Panics if there are already 65535 content IDs present, see [`MapBlock::content_id_headroom`]
     fn add_content(&mut self, content: Vec<u8>) -> u16 {
         for id in u16::MIN..u16::MAX {
             match self.name_id_mappings.entry(id) {
@@ -375,24 +1089,111 @@ impl MapBlock {
             .unwrap_or_else(|| self.add_content(content.to_vec()))
     }
 
+    /// Replaces the content type `from` with `to` for all nodes of this block
+    ///
+    /// Only the name-id mappings are changed, unless `to` is already present in the block.
+    /// In that case, the content IDs of the affected nodes are merged into the existing one.
+    /// Returns whether any content was renamed.
+    pub fn rename_content(&mut self, from: &[u8], to: &[u8]) -> bool {
+        let Some(from_id) = self.get_content_id(from) else {
+            return false;
+        };
+        if from == to {
+            return false;
+        }
+        match self.get_content_id(to) {
+            Some(to_id) => {
+                self.name_id_mappings.remove(&from_id);
+                for content_id in self.param0.iter_mut().filter(|id| **id == from_id) {
+                    *content_id = to_id;
+                }
+            }
+            None => {
+                self.name_id_mappings.insert(from_id, to.to_vec());
+            }
+        }
+        true
+    }
+
+    /// Returns the array index of a node, if its position lies within the map block
+    fn checked_node_index(relative_node_pos: Position) -> Result<usize, OutOfBlockError> {
+        let range = 0..MAPBLOCK_LENGTH as i16;
+        let Position { x, y, z } = relative_node_pos;
+        if range.contains(&x) && range.contains(&y) && range.contains(&z) {
+            Ok(relative_node_pos.as_node_index() as usize)
+        } else {
+            Err(OutOfBlockError(relative_node_pos))
+        }
+    }
+
     /// Sets the content type of this node
+    ///
+    /// The caller guarantees that `relative_node_pos` lies within the map block.
+    /// Positions outside of it silently wrap around; use [`MapBlock::try_set_content`]
+    /// to have them rejected.
     pub fn set_content(&mut self, relative_node_pos: Position, content_id: u16) {
         let index = relative_node_pos.as_node_index() as usize % MAPBLOCK_SIZE;
         self.param0[index] = content_id
     }
 
+    /// Sets the content type of this node, if the position lies within the map block
+    ///
+    /// ```
+    /// use minetestworld::{MapBlock, Position};
+    ///
+    /// let mut block = MapBlock::unloaded();
+    /// assert!(block.try_set_content(Position::new(15i16, 0, 0), 0).is_ok());
+    /// assert!(block.try_set_content(Position::new(16i16, 0, 0), 0).is_err());
+    /// ```
+    pub fn try_set_content(
+        &mut self,
+        relative_node_pos: Position,
+        content_id: u16,
+    ) -> Result<(), OutOfBlockError> {
+        self.param0[Self::checked_node_index(relative_node_pos)?] = content_id;
+        Ok(())
+    }
+
     /// Sets the param1 of this node
+    ///
+    /// The caller guarantees that `relative_node_pos` lies within the map block.
+    /// Positions outside of it silently wrap around; use [`MapBlock::try_set_param1`]
+    /// to have them rejected.
     pub fn set_param1(&mut self, relative_node_pos: Position, param1: u8) {
         let index = relative_node_pos.as_node_index() as usize % MAPBLOCK_SIZE;
         self.param1[index] = param1
     }
 
+    /// Sets the param1 of this node, if the position lies within the map block
+    pub fn try_set_param1(
+        &mut self,
+        relative_node_pos: Position,
+        param1: u8,
+    ) -> Result<(), OutOfBlockError> {
+        self.param1[Self::checked_node_index(relative_node_pos)?] = param1;
+        Ok(())
+    }
+
     /// Sets the param2 of this node
+    ///
+    /// The caller guarantees that `relative_node_pos` lies within the map block.
+    /// Positions outside of it silently wrap around; use [`MapBlock::try_set_param2`]
+    /// to have them rejected.
     pub fn set_param2(&mut self, relative_node_pos: Position, param2: u8) {
         let index = relative_node_pos.as_node_index() as usize % MAPBLOCK_SIZE;
         self.param2[index] = param2
     }
 
+    /// Sets the param2 of this node, if the position lies within the map block
+    pub fn try_set_param2(
+        &mut self,
+        relative_node_pos: Position,
+        param2: u8,
+    ) -> Result<(), OutOfBlockError> {
+        self.param2[Self::checked_node_index(relative_node_pos)?] = param2;
+        Ok(())
+    }
+
     /// Returns an iterator over all content types that appear in name-id-mapping
     ///
     /// Example:
@@ -406,10 +1207,187 @@ impl MapBlock {
     pub fn content_names(&self) -> impl Iterator<Item = &[u8]> {
         self.name_id_mappings.values().map(Vec::as_slice)
     }
+
+    /// Returns the node all nodes of this block consist of, if they are identical
+    ///
+    /// This holds if `param0`, `param1`, and `param2` each have the same value for every node.
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// let node = MapBlock::unloaded().is_uniform().unwrap();
+    /// assert_eq!(node.param0, b"ignore");
+    /// ```
+    pub fn is_uniform(&self) -> Option<Node> {
+        fn all_equal<T: PartialEq>(values: &[T]) -> bool {
+            values.iter().all(|v| v == &values[0])
+        }
+
+        if all_equal(&self.param0) && all_equal(&self.param1) && all_equal(&self.param2) {
+            Some(self.get_node_at(Position::new(0i16, 0, 0)))
+        } else {
+            None
+        }
+    }
+
+    /// Gives an overview of the block's contents
+    ///
+    /// ```
+    /// use minetestworld::MapBlock;
+    ///
+    /// let summary = MapBlock::unloaded().summary();
+    /// assert_eq!(summary.content_counts, vec![(b"ignore".to_vec(), 4096)]);
+    /// println!("{summary}");
+    /// ```
+    pub fn summary(&self) -> BlockSummary {
+        let mut id_counts: HashMap<u16, u32> = HashMap::new();
+        for &content_id in self.param0.iter() {
+            *id_counts.entry(content_id).or_default() += 1;
+        }
+        // Different IDs may map to the same content type, so count by content type
+        let mut counts: HashMap<&[u8], u32> = HashMap::new();
+        for (content_id, count) in id_counts {
+            *counts.entry(self.content_from_id(content_id)).or_default() += count;
+        }
+        let mut content_counts: Vec<(Vec<u8>, u32)> = counts
+            .into_iter()
+            .map(|(content, count)| (content.to_vec(), count))
+            .collect();
+        content_counts.sort_by(|(a_content, a_count), (b_content, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_content.cmp(b_content))
+        });
+        BlockSummary {
+            distinct_contents: content_counts.len(),
+            content_counts,
+            metadata: self.node_metadata.len(),
+            timers: self.node_timers.len(),
+            objects: self.static_objects.len(),
+            timestamp: self.timestamp,
+            underground: self.is_underground(),
+        }
+    }
+}
+
+/// Constructs a [`MapBlock`] from scratch with fluent setters
+///
+/// The builder starts with a generated block of air nodes, like
+/// [`MapBlock::new_filled`]: Its timestamp is zero and lighting is complete on all sides.
+///
+/// ```
+/// use minetestworld::{MapBlock, Node, Position};
+/// use minetestworld::map_block::MapBlockBuilder;
+///
+/// let stone = Node {
+///     param0: b"default:stone".to_vec(),
+///     param1: 0,
+///     param2: 0,
+/// };
+/// let block = MapBlockBuilder::new()
+///     .timestamp(1000)
+///     .underground(true)
+///     .node(Position::new(1i16, 2, 3), &stone)
+///     .build();
+/// assert_eq!(block.get_node_at(Position::new(1i16, 2, 3)), stone);
+/// ```
+#[derive(Debug)]
+pub struct MapBlockBuilder {
+    mapblock: MapBlock,
+}
+
+impl MapBlockBuilder {
+    /// Starts building a block that consists of air
+    pub fn new() -> Self {
+        MapBlockBuilder {
+            mapblock: MapBlock::new_filled(&Node {
+                param0: CONTENT_AIR.to_vec(),
+                param1: 0,
+                param2: 0,
+            }),
+        }
+    }
+
+    /// Sets the timestamp of last save, in seconds from game start
+    pub fn timestamp(mut self, timestamp: u32) -> Self {
+        self.mapblock.timestamp = timestamp;
+        self
+    }
+
+    /// Sets all flags at once, see [`MapBlock::flags`]
+    pub fn flags(mut self, flags: u8) -> Self {
+        self.mapblock.flags = flags;
+        self
+    }
+
+    /// Sets whether the block is underground, see [`MapBlock::is_underground`]
+    pub fn underground(mut self, underground: bool) -> Self {
+        self.mapblock.set_underground(underground);
+        self
+    }
+
+    /// Sets whether the lighting differs between day and night, see [`MapBlock::day_night_differs`]
+    pub fn day_night_differs(mut self, differs: bool) -> Self {
+        self.mapblock.set_day_night_differs(differs);
+        self
+    }
+
+    /// Sets the flags that indicate if the lighting is complete at each side
+    pub fn lighting_complete(mut self, lighting_complete: u16) -> Self {
+        self.mapblock.lighting_complete = lighting_complete;
+        self
+    }
+
+    /// Replaces all nodes with `node`, see [`MapBlock::fill`]
+    pub fn fill(mut self, node: &Node) -> Self {
+        self.mapblock.fill(node);
+        self
+    }
+
+    /// Sets the node at a mapblock-relative position
+    ///
+    /// Panics if the position lies outside of the map block.
+    pub fn node(mut self, relative_node_pos: Position, node: &Node) -> Self {
+        let index = match MapBlock::checked_node_index(relative_node_pos) {
+            Ok(index) => index,
+            Err(e) => panic!("{e}"),
+        };
+        let content_id = self.mapblock.get_or_create_content_id(&node.param0);
+        self.mapblock.param0[index] = content_id;
+        self.mapblock.param1[index] = node.param1;
+        self.mapblock.param2[index] = node.param2;
+        self
+    }
+
+    /// Returns the constructed block
+    pub fn build(self) -> MapBlock {
+        self.mapblock
+    }
+}
+
+impl Default for MapBlockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Helper functions to read and write smaller chunks of binary data
 
+fn too_many(count: usize, what: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{count} {what} do not fit into the map block format"),
+    )
+}
+
+/// Converts a count or length for serialization, failing instead of truncating it
+fn checked_u16(count: usize, what: &str) -> std::io::Result<u16> {
+    u16::try_from(count).map_err(|_| too_many(count, what))
+}
+
+/// Converts a count or length for serialization, failing instead of truncating it
+fn checked_u32(count: usize, what: &str) -> std::io::Result<u32> {
+    u32::try_from(count).map_err(|_| too_many(count, what))
+}
+
 fn read_name_id_mappings(data: &mut impl Read) -> Result<NameIdMappings, MapBlockError> {
     if read_u8(data)? != 0 {
         return Err(MapBlockError::BlobMalformed(
@@ -421,8 +1399,8 @@ fn read_name_id_mappings(data: &mut impl Read) -> Result<NameIdMappings, MapBloc
     let mut name_id_mappings = HashMap::new();
     for _ in 0..num_name_id_mappings {
         let id = read_u16_be(data)?;
-        let mut name = vec![0; read_u16_be(data)? as usize];
-        data.read_exact(&mut name)?;
+        let name_len = read_u16_be(data)? as usize;
+        let name = read_bytes(data, name_len, "content name")?;
 
         if let Some(old_name) = name_id_mappings.insert(id, name.clone()) {
             return Err(MapBlockError::BlobMalformed(format!(
@@ -437,35 +1415,48 @@ fn read_name_id_mappings(data: &mut impl Read) -> Result<NameIdMappings, MapBloc
 
 fn write_name_id_mappings(mappings: &NameIdMappings, dest: &mut impl Write) -> std::io::Result<()> {
     dest.write_all(&[0])?; // Version byte
-    dest.write_all(&(mappings.len() as u16).to_be_bytes())?; // TODO handle length greater than 65k
+    dest.write_all(&checked_u16(mappings.len(), "name-id mappings")?.to_be_bytes())?;
     for (key, value) in mappings {
         dest.write_all(&key.to_be_bytes())?;
-        dest.write_all(&(value.len() as u16).to_be_bytes())?;
+        dest.write_all(&checked_u16(value.len(), "content name bytes")?.to_be_bytes())?;
         dest.write_all(value)?;
     }
     Ok(())
 }
 
-fn read_inventory(data: &mut impl Read) -> std::io::Result<Vec<u8>> {
+/// Upper bound for the serialized size of an inventory
+///
+/// Real inventories are far smaller. Without a bound, a missing `EndInventory`
+/// would swallow the rest of a corrupt block.
+const MAX_INVENTORY_LEN: usize = 1 << 20;
+
+pub(crate) fn read_inventory(data: &mut impl Read) -> Result<Vec<u8>, MapBlockError> {
     let mut result = vec![];
-    let mut line = vec![];
-
-    for byte in data.bytes() {
-        let byte = byte?;
-        line.push(byte);
-        if byte == 10 {
-            result.extend_from_slice(&line);
-            if line == b"EndInventory\n" {
+    let mut line_start = 0;
+
+    loop {
+        let byte = match read_u8(data) {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(MapBlockError::BlobMalformed(
+                    "inventory is not terminated by EndInventory".into(),
+                ))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        result.push(byte);
+        if byte == b'\n' {
+            if result[line_start..] == *b"EndInventory\n" {
                 return Ok(result);
             }
-            line.clear();
+            line_start = result.len();
+        }
+        if result.len() >= MAX_INVENTORY_LEN {
+            return Err(MapBlockError::BlobMalformed(format!(
+                "inventory exceeds {MAX_INVENTORY_LEN} bytes without EndInventory"
+            )));
         }
     }
-
-    Err(std::io::Error::new(
-        std::io::ErrorKind::UnexpectedEof,
-        "inventory",
-    ))
 }
 
 fn read_node_metadata(data: &mut impl Read) -> Result<Vec<NodeMetadata>, MapBlockError> {
@@ -479,7 +1470,7 @@ fn read_node_metadata(data: &mut impl Read) -> Result<Vec<NodeMetadata>, MapBloc
         ));
     }
     let metadata_count = read_u16_be(data)?;
-    let metadata = Vec::with_capacity(metadata_count as usize);
+    let mut metadata = Vec::with_capacity((metadata_count as usize).min(MAX_PREALLOCATION));
 
     for _ in 0..metadata_count {
         let mut metadatum = NodeMetadata {
@@ -490,10 +1481,10 @@ fn read_node_metadata(data: &mut impl Read) -> Result<Vec<NodeMetadata>, MapBloc
 
         let var_count = read_u32_be(data)?;
         for _ in 0..var_count {
-            let mut key = vec![0; read_u16_be(data)? as usize];
-            data.read_exact(&mut key)?;
-            let mut value = vec![0; read_u32_be(data)? as usize];
-            data.read_exact(&mut value)?;
+            let key_len = read_u16_be(data)? as usize;
+            let key = read_bytes(data, key_len, "metadata key")?;
+            let value_len = read_u32_be(data)? as usize;
+            let value = read_bytes(data, value_len, "metadata value")?;
             let is_private = read_u8(data)?;
             if is_private > 1 {
                 return Err(MapBlockError::BlobMalformed(
@@ -508,6 +1499,7 @@ fn read_node_metadata(data: &mut impl Read) -> Result<Vec<NodeMetadata>, MapBloc
             });
         }
         metadatum.inventory = read_inventory(data)?;
+        metadata.push(metadatum);
     }
 
     Ok(metadata)
@@ -518,13 +1510,16 @@ fn write_node_metadata(data: &[NodeMetadata], dest: &mut impl Write) -> std::io:
         dest.write_all(&[0])?;
     } else {
         dest.write_all(&[2])?;
-        dest.write_all(&(data.len() as u16).to_be_bytes())?; // TODO handle count greater than 65k
+        dest.write_all(&checked_u16(data.len(), "node metadata")?.to_be_bytes())?;
         for metadatum in data {
             dest.write_all(&metadatum.position.as_node_index().to_be_bytes())?;
+            dest.write_all(&checked_u32(metadatum.vars.len(), "metadata vars")?.to_be_bytes())?;
             for var in &metadatum.vars {
-                dest.write_all(&(var.key.len() as u16).to_be_bytes())?;
+                dest.write_all(&checked_u16(var.key.len(), "metadata key bytes")?.to_be_bytes())?;
                 dest.write_all(&var.key)?;
-                dest.write_all(&(var.value.len() as u32).to_be_bytes())?;
+                dest.write_all(
+                    &checked_u32(var.value.len(), "metadata value bytes")?.to_be_bytes(),
+                )?;
                 dest.write_all(&var.value)?;
                 dest.write_all(&[var.is_private as u8])?;
             }
@@ -555,14 +1550,13 @@ fn read_static_objects(source: &mut impl Read) -> Result<Vec<StaticObject>, MapB
         )));
     }
     let count = read_u16_be(source)?;
-    let mut objects = Vec::with_capacity(count as usize);
+    let mut objects = Vec::with_capacity((count as usize).min(MAX_PREALLOCATION));
 
     for _ in 0..count {
         let type_id = read_u8(source)?;
         let (x, y, z) = read_object_pos(source)?;
         let data_size = read_u16_be(source)?;
-        let mut data = vec![0; data_size as usize];
-        source.read_exact(&mut data)?;
+        let data = read_bytes(source, data_size as usize, "static object data")?;
         objects.push(StaticObject {
             type_id,
             x,
@@ -577,12 +1571,13 @@ fn read_static_objects(source: &mut impl Read) -> Result<Vec<StaticObject>, MapB
 
 fn write_static_objects(data: &[StaticObject], dest: &mut impl Write) -> std::io::Result<()> {
     dest.write_all(&[0])?;
-    dest.write_all(&(data.len() as u16).to_be_bytes())?;
+    dest.write_all(&checked_u16(data.len(), "static objects")?.to_be_bytes())?;
     for object in data {
+        dest.write_all(&[object.type_id])?;
         for i in [object.x, object.y, object.z] {
             dest.write_all(&i.to_be_bytes())?;
         }
-        dest.write_all(&(object.data.len() as u16).to_be_bytes())?;
+        dest.write_all(&checked_u16(object.data.len(), "static object data bytes")?.to_be_bytes())?;
         dest.write_all(&object.data)?;
     }
     Ok(())
@@ -598,7 +1593,7 @@ fn read_timers(data: &mut impl Read) -> Result<Vec<NodeTimer>, MapBlockError> {
     }
 
     let count = read_u16_be(data)?;
-    let mut timers = Vec::with_capacity(count as usize);
+    let mut timers = Vec::with_capacity((count as usize).min(MAX_PREALLOCATION));
 
     for _ in 0..count {
         let position = Position::from_node_index(read_u16_be(data)?);
@@ -616,7 +1611,7 @@ fn read_timers(data: &mut impl Read) -> Result<Vec<NodeTimer>, MapBlockError> {
 
 fn write_node_timers(data: &[NodeTimer], dest: &mut impl Write) -> std::io::Result<()> {
     dest.write_all(&[10])?; // Data length of node timers
-    dest.write_all(&(data.len() as u16).to_be_bytes())?;
+    dest.write_all(&checked_u16(data.len(), "node timers")?.to_be_bytes())?;
     for timer in data {
         dest.write_all(&timer.position.as_node_index().to_be_bytes())?;
         dest.write_all(&timer.timeout.to_be_bytes())?;
@@ -637,6 +1632,15 @@ pub struct NodeIter {
 }
 
 impl NodeIter {
+    #[cfg_attr(
+        not(any(
+            feature = "sqlite",
+            feature = "postgres",
+            feature = "redis",
+            feature = "experimental-leveldb"
+        )),
+        allow(dead_code)
+    )]
     pub(crate) fn from(mapblock: MapBlock, mapblock_position: Position) -> Self {
         NodeIter {
             mapblock,
@@ -644,29 +1648,78 @@ impl NodeIter {
             node_index: 0,
         }
     }
-}
 
-impl Iterator for NodeIter {
-    /// A tuple consisting of the node and its position in the world.
-    type Item = (Position, Node);
+    /// Turns this into an iterator that reports content IDs missing from the name-id mapping
+    ///
+    /// See [`CheckedNodeIter`].
+    pub fn checked(self) -> CheckedNodeIter {
+        CheckedNodeIter(self)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Advances to the next node, returning its world position and node index
+    fn next_index(&mut self) -> Option<(Position, usize)> {
         let index = self.node_index;
         if index < 4096 {
             self.node_index += 1;
             let pos =
                 self.mapblock_position * MAPBLOCK_LENGTH as i16 + Position::from_node_index(index);
-            let param0 = self
-                .mapblock
-                .content_from_id(self.mapblock.param0[index as usize]);
-            let node = Node {
-                param0: param0.to_vec(),
-                param1: self.mapblock.param1[index as usize],
-                param2: self.mapblock.param2[index as usize],
-            };
-            Some((pos, node))
+            Some((pos, index as usize))
         } else {
             None
         }
     }
+
+    fn node_with_content(&self, index: usize, param0: &[u8]) -> Node {
+        Node {
+            param0: param0.to_vec(),
+            param1: self.mapblock.param1[index],
+            param2: self.mapblock.param2[index],
+        }
+    }
+}
+
+impl Iterator for NodeIter {
+    /// A tuple consisting of the node and its position in the world.
+    type Item = (Position, Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (pos, index) = self.next_index()?;
+        let param0 = self.mapblock.content_from_id(self.mapblock.param0[index]);
+        Some((pos, self.node_with_content(index, param0)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = MAPBLOCK_SIZE - self.node_index as usize;
+        (remaining, Some(remaining))
+    }
 }
+
+impl ExactSizeIterator for NodeIter {}
+
+/// Iterates through the nodes in a mapblock, reporting dangling content IDs
+///
+/// Like [`NodeIter`], but a node whose `param0` is missing in the name-id mapping
+/// is yielded as `Err` with that content ID, instead of as [`CONTENT_UNKNOWN`].
+/// This lets verification tools flag corrupted blocks.
+pub struct CheckedNodeIter(NodeIter);
+
+impl Iterator for CheckedNodeIter {
+    /// A tuple consisting of the node or its dangling content ID, and its position in the world.
+    type Item = (Position, Result<Node, u16>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (pos, index) = self.0.next_index()?;
+        let content_id = self.0.mapblock.param0[index];
+        let node = match self.0.mapblock.content_from_id_opt(content_id) {
+            Some(param0) => Ok(self.0.node_with_content(index, param0)),
+            None => Err(content_id),
+        };
+        Some((pos, node))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for CheckedNodeIter {}
@@ -1,7 +1,7 @@
 //! Contains a type to read a world's map data
-#[cfg(feature = "experimental-leveldb")]
-use async_std::sync::{Arc, Mutex};
+use crate::runtime::spawn_blocking;
 use futures::future;
+use futures::io::{AsyncWrite, AsyncWriteExt};
 use futures::stream;
 use futures::stream::BoxStream;
 use futures::stream::StreamExt;
@@ -12,26 +12,74 @@ use log::LevelFilter;
 #[cfg(feature = "redis")]
 use redis::{aio::MultiplexedConnection as RedisConn, AsyncCommands};
 #[cfg(feature = "sqlite")]
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
 #[cfg(feature = "postgres")]
-use sqlx::{postgres::PgConnectOptions, PgPool};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    PgPool,
+};
 #[cfg(any(feature = "sqlite", feature = "postgres"))]
 use sqlx::{prelude::*, ConnectOptions};
 #[cfg(any(feature = "sqlite", feature = "experimental-leveldb"))]
 use std::path::Path;
 use std::str::FromStr;
+#[cfg(feature = "experimental-leveldb")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "sqlite")]
+use std::time::Duration;
 #[cfg(feature = "redis")]
 use url::Host;
 
-use crate::map_block::{MapBlock, MapBlockError, Node, NodeIter};
-use crate::positions::Position;
+use crate::map_block::{MapBlock, MapBlockError, Node, NodeIter, CONTENT_AIR, MAPBLOCK_LENGTH};
+use crate::positions::{Position, PositionRangeError};
 
+#[cfg(feature = "postgres")]
 const POSTGRES_QUERY: &str = "SELECT data FROM blocks
  WHERE (posx = $1 AND posy = $2 AND posz = $3)";
 
+#[cfg(feature = "postgres")]
+const POSTGRES_CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS blocks (
+ posx INT NOT NULL, posy INT NOT NULL, posz INT NOT NULL, data BYTEA,
+ PRIMARY KEY (posx, posy, posz))";
+
+#[cfg(feature = "postgres")]
+const POSTGRES_COLUMNS: &str = "SELECT column_name::TEXT FROM information_schema.columns
+ WHERE table_schema = current_schema() AND table_name = 'blocks'";
+
+/// The columns of the `blocks` table that the Postgres backend uses
+#[cfg(feature = "postgres")]
+const POSTGRES_REQUIRED_COLUMNS: [&str; 4] = ["posx", "posy", "posz", "data"];
+
+#[cfg(feature = "postgres")]
+const POSTGRES_POSITIONS_SORTED: &str = "SELECT DISTINCT posx, posy, posz FROM blocks
+ ORDER BY posx, posy, posz";
+
+/// How many hash entries Redis should return per HSCAN call, as a hint
+#[cfg(feature = "redis")]
+const REDIS_SCAN_COUNT: usize = 1000;
+
+// MIN and MAX are NULL for an empty table, so no rows are returned in this case
+#[cfg(feature = "postgres")]
+const POSTGRES_BOUNDS: &str = "SELECT MIN(posx) AS posx, MIN(posy) AS posy, MIN(posz) AS posz
+ FROM blocks HAVING COUNT(*) > 0
+ UNION ALL SELECT MAX(posx), MAX(posy), MAX(posz) FROM blocks HAVING COUNT(*) > 0";
+
+#[cfg(feature = "sqlite")]
+const SQLITE_EXISTS: &str = "SELECT 1 FROM blocks WHERE pos = ? LIMIT 1";
+
+#[cfg(feature = "postgres")]
+const POSTGRES_EXISTS: &str = "SELECT 1 FROM blocks
+ WHERE (posx = $1 AND posy = $2 AND posz = $3) LIMIT 1";
+
+#[cfg(feature = "postgres")]
+const POSTGRES_DELETE: &str = "DELETE FROM blocks
+ WHERE (posx = $1 AND posy = $2 AND posz = $3)";
+
+#[cfg(feature = "sqlite")]
 const SQLITE_UPSERT: &str = "INSERT INTO blocks VALUES (?, ?)
  ON CONFLICT(pos) DO UPDATE SET data=excluded.data";
 
+#[cfg(feature = "postgres")]
 const POSTGRES_UPSERT: &str = "INSERT INTO blocks VALUES($1, $2, $3, $4)
  ON CONFLICT(posx,posy,posz) DO UPDATE SET data=excluded.data";
 
@@ -61,6 +109,31 @@ pub enum MapDataError {
     #[error("MapBlock {0:?} does not exist")]
     MapBlockNonexistent(Position),
 
+    /// The mapblock position cannot be stored in the backend
+    #[error("{0}")]
+    PositionOutOfRange(#[from] PositionRangeError),
+
+    /// The data stored for this mapblock could not be decoded
+    ///
+    /// The inner error tells whether the block is corrupt or has an unsupported version.
+    #[error("MapBlock {0:?} could not be decoded: {1}")]
+    InvalidMapBlock(Position, #[source] MapBlockError),
+
+    /// The `blocks` table of a Postgres database lacks some of the required columns
+    ///
+    /// The columns the table actually has are contained.
+    #[cfg(feature = "postgres")]
+    #[error(
+        "Incompatible database schema: the blocks table needs the columns posx, posy, posz, \
+         and data, but has {0:?}"
+    )]
+    IncompatiblePostgresSchema(Vec<String>),
+
+    /// The data read back after [writing](`MapData::set_mapblock_checked`) a mapblock
+    /// differs from the written data
+    #[error("MapBlock {0:?} could not be read back as written")]
+    WriteVerificationFailed(Position),
+
     /// An IO related error
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
@@ -80,9 +153,120 @@ impl MapDataError {
     }
 }
 
+/// Makes sure that a Postgres `blocks` table with these columns can be used
+#[cfg(feature = "postgres")]
+pub(crate) fn check_postgres_columns(columns: Vec<String>) -> Result<(), MapDataError> {
+    if POSTGRES_REQUIRED_COLUMNS
+        .iter()
+        .all(|required| columns.iter().any(|column| column == required))
+    {
+        Ok(())
+    } else {
+        Err(MapDataError::IncompatiblePostgresSchema(columns))
+    }
+}
+
+#[cfg(feature = "postgres")]
+async fn check_postgres_schema(pool: &PgPool) -> Result<(), MapDataError> {
+    let columns = sqlx::query_scalar(POSTGRES_COLUMNS).fetch_all(pool).await?;
+    check_postgres_columns(columns)
+}
+
+/// Minetest's LevelDB backend uses the decimal database key as key
+#[cfg(feature = "experimental-leveldb")]
+fn leveldb_key(pos: Position) -> Result<Vec<u8>, PositionRangeError> {
+    Ok(pos.try_as_database_key()?.to_string().into_bytes())
+}
+
+#[cfg(feature = "experimental-leveldb")]
+fn leveldb_key_to_position(key: &[u8]) -> Option<Position> {
+    let key = std::str::from_utf8(key).ok()?.parse().ok()?;
+    Some(Position::from_database_key(key))
+}
+
+/// Options for connecting to a SQLite map database
+///
+/// See [`MapData::from_sqlite_file_with_opts`].
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone)]
+pub struct SqliteOptions {
+    /// Switch the database to write-ahead logging
+    ///
+    /// This allows reading while another connection writes.
+    /// If `false` (the default), the journal mode of the database is left as it is.
+    pub wal: bool,
+    /// How long to wait for a lock held by another connection before failing
+    ///
+    /// Defaults to 5 seconds.
+    pub busy_timeout: Duration,
+    /// The maximum number of connections in the pool
+    ///
+    /// Defaults to 10. A single connection suits single-threaded tools
+    /// and avoids lock contention between reading and writing connections.
+    pub max_connections: u32,
+}
+
+#[cfg(feature = "sqlite")]
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        SqliteOptions {
+            wal: false,
+            busy_timeout: Duration::from_secs(5),
+            max_connections: 10,
+        }
+    }
+}
+
+/// Options for connecting to a PostgreSQL map database
+///
+/// See [`MapData::from_pg_connection_params_with_opts`].
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone)]
+pub struct PostgresOptions {
+    /// The maximum number of connections in the pool
+    ///
+    /// Defaults to 10. Raise it for highly concurrent readers like renderers.
+    pub max_connections: u32,
+}
+
+#[cfg(feature = "postgres")]
+impl Default for PostgresOptions {
+    fn default() -> Self {
+        PostgresOptions {
+            max_connections: 10,
+        }
+    }
+}
+
+/// The result of [`MapData::verify`]
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// The number of blocks that were decoded successfully
+    pub ok: u64,
+    /// The positions of all blocks that could not be decoded, together with the reason
+    pub errors: Vec<(Position, MapBlockError)>,
+}
+
+/// Counts the decodable blocks of a stream and collects the errors of the others
+async fn verify_blocks(
+    mut blocks: BoxStream<'_, Result<(Position, MapBlock), MapDataError>>,
+) -> Result<VerifyReport, MapDataError> {
+    let mut report = VerifyReport::default();
+    while let Some(result) = blocks.next().await {
+        match result {
+            Ok(_) => report.ok += 1,
+            Err(MapDataError::InvalidMapBlock(pos, e)) => report.errors.push((pos, e)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(report)
+}
+
 /// A handle to the world data
 ///
 /// Can be used to query MapBlocks and nodes.
+/// Clones share the same connection pool.
+#[derive(Clone)]
 pub enum MapData {
     /// This variant covers the SQLite database backend
     #[cfg(feature = "sqlite")]
@@ -102,6 +286,8 @@ pub enum MapData {
     },
 
     /// This variant is a thread-safe open LevelDB
+    ///
+    /// As LevelDB blocks, it is only accessed from blocking tasks.
     #[cfg(feature = "experimental-leveldb")]
     LevelDb(Arc<Mutex<LevelDb>>),
 }
@@ -114,7 +300,14 @@ impl MapData {
     ///
     /// ```
     /// use minetestworld::MapData;
+    /// # #[cfg(feature = "async-std")]
     /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
     ///
     /// let meta = task::block_on(async {
     ///     MapData::from_sqlite_file("TestWorld/map.sqlite", false).await.unwrap();
@@ -124,12 +317,104 @@ impl MapData {
         filename: impl AsRef<Path>,
         read_only: bool,
     ) -> Result<MapData, MapDataError> {
-        let opts = SqliteConnectOptions::new()
+        Self::from_sqlite_file_with_opts(filename, read_only, SqliteOptions::default()).await
+    }
+
+    #[cfg(feature = "sqlite")]
+    /// Connects to the "map.sqlite" database with the given options
+    ///
+    /// Enabling [WAL](`SqliteOptions::wal`) and a generous
+    /// [busy timeout](`SqliteOptions::busy_timeout`) avoids "database is locked" errors
+    /// when other processes access the database at the same time.
+    ///
+    /// ```
+    /// use minetestworld::map_data::{MapData, SqliteOptions};
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    /// use std::time::Duration;
+    ///
+    /// let opts = SqliteOptions {
+    ///     busy_timeout: Duration::from_secs(30),
+    ///     ..Default::default()
+    /// };
+    /// task::block_on(async {
+    ///     MapData::from_sqlite_file_with_opts("TestWorld/map.sqlite", true, opts).await.unwrap();
+    /// });
+    /// ```
+    pub async fn from_sqlite_file_with_opts(
+        filename: impl AsRef<Path>,
+        read_only: bool,
+        options: SqliteOptions,
+    ) -> Result<MapData, MapDataError> {
+        let mut opts = SqliteConnectOptions::new()
             .immutable(read_only)
             .filename(filename)
             .create_if_missing(!read_only)
+            .busy_timeout(options.busy_timeout)
+            .log_statements(LevelFilter::Debug);
+        if options.wal {
+            opts = opts.journal_mode(SqliteJournalMode::Wal);
+        }
+        Self::from_sqlite_connect_options(opts, options.max_connections).await
+    }
+
+    #[cfg(feature = "sqlite")]
+    /// Connects to an SQLite database given by an [SQLite URI](https://www.sqlite.org/uri.html)
+    ///
+    /// This allows to set the query parameters `mode`, `cache`, `immutable`, and `vfs`,
+    /// e.g. to read a world on a read-only network mount.
+    /// Both `file:` and `sqlite:` URIs are accepted. With `read_only`, the database is
+    /// opened read-only regardless of the `mode` parameter.
+    ///
+    /// ```
+    /// use minetestworld::MapData;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// task::block_on(async {
+    ///     MapData::from_sqlite_uri("file:TestWorld/map.sqlite?mode=ro&immutable=1", true)
+    ///         .await
+    ///         .unwrap();
+    /// });
+    /// ```
+    pub async fn from_sqlite_uri(uri: &str, read_only: bool) -> Result<MapData, MapDataError> {
+        // sqlx only knows the `sqlite:` scheme; the rest of the URI is the same
+        let uri = match uri.strip_prefix("file:") {
+            Some(rest) => format!("sqlite:{}", rest.strip_prefix("//").unwrap_or(rest)),
+            None => uri.to_string(),
+        };
+        let mut opts = SqliteConnectOptions::from_str(&uri)?
+            .busy_timeout(SqliteOptions::default().busy_timeout)
             .log_statements(LevelFilter::Debug);
-        match SqlitePool::connect_with(opts).await {
+        if read_only {
+            opts = opts.read_only(true);
+        }
+        let max_connections = SqliteOptions::default().max_connections;
+        Self::from_sqlite_connect_options(opts, max_connections).await
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn from_sqlite_connect_options(
+        opts: SqliteConnectOptions,
+        max_connections: u32,
+    ) -> Result<MapData, MapDataError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(opts)
+            .await;
+        match pool {
             Ok(pool) => {
                 sqlx::query("CREATE TABLE IF NOT EXISTS blocks (`pos` INT NOT NULL PRIMARY KEY,`data` BLOB)").execute(&pool).await?;
                 Ok(MapData::Sqlite(pool))
@@ -141,8 +426,35 @@ impl MapData {
     #[cfg(feature = "postgres")]
     /// Connects to a Postgres database
     pub async fn from_pg_connection_params(url: &str) -> Result<MapData, MapDataError> {
+        Self::from_pg_connection_params_with_opts(url, PostgresOptions::default()).await
+    }
+
+    #[cfg(feature = "postgres")]
+    /// Connects to a Postgres database with the given options
+    ///
+    /// Fails with [`MapDataError::IncompatiblePostgresSchema`] if the `blocks` table
+    /// lacks one of the columns `posx`, `posy`, `posz`, and `data`.
+    pub async fn from_pg_connection_params_with_opts(
+        url: &str,
+        options: PostgresOptions,
+    ) -> Result<MapData, MapDataError> {
         let opts = PgConnectOptions::from_str(url)?.log_statements(LevelFilter::Debug);
-        Ok(MapData::Postgres(PgPool::connect_with(opts).await?))
+        let pool = PgPoolOptions::new()
+            .max_connections(options.max_connections)
+            .connect_with(opts)
+            .await?;
+        check_postgres_schema(&pool).await?;
+        Ok(MapData::Postgres(pool))
+    }
+
+    #[cfg(feature = "postgres")]
+    /// Connects to a Postgres database and creates the `blocks` table if it does not exist
+    pub async fn create_postgres_schema(url: &str) -> Result<MapData, MapDataError> {
+        let opts = PgConnectOptions::from_str(url)?.log_statements(LevelFilter::Debug);
+        let pool = PgPool::connect_with(opts).await?;
+        sqlx::query(POSTGRES_CREATE_TABLE).execute(&pool).await?;
+        check_postgres_schema(&pool).await?;
+        Ok(MapData::Postgres(pool))
     }
 
     #[cfg(feature = "redis")]
@@ -157,7 +469,7 @@ impl MapData {
                 "redis://{host}{}/",
                 port.map(|p| format!(":{p}")).unwrap_or_default()
             ))?
-            .get_multiplexed_async_std_connection()
+            .get_multiplexed_async_connection()
             .await?,
             hash: hash.to_string(),
         })
@@ -174,7 +486,13 @@ impl MapData {
     ///
     /// Note that the unit of the coordinates will be
     /// [MAPBLOCK_LENGTH][`crate::map_block::MAPBLOCK_LENGTH`].
-    pub async fn all_mapblock_positions(&self) -> BoxStream<Result<Position, MapDataError>> {
+    ///
+    /// The positions are yielded in no particular order, as they come from the backend.
+    /// If an SQL table lacks its primary key, a position may be yielded more than once.
+    /// Redis positions are scanned in batches while the stream is polled; a block that is added
+    /// or removed meanwhile may be missing, and a position may be yielded twice.
+    /// Use [`MapData::all_mapblock_positions_sorted`] for a reproducible order without duplicates.
+    pub async fn all_mapblock_positions(&self) -> BoxStream<'_, Result<Position, MapDataError>> {
         match self {
             #[cfg(feature = "sqlite")]
             MapData::Sqlite(pool) => sqlx::query_as("SELECT pos FROM blocks")
@@ -188,50 +506,191 @@ impl MapData {
                 .boxed(),
             #[cfg(feature = "redis")]
             MapData::Redis { connection, hash } => {
-                // We can't really stream, so we'll just collect the result with hkeys
-                let positions: Result<Vec<i64>, _> =
-                    connection.clone().hkeys(hash.to_string()).await;
+                // Fetch the keys in batches with HSCAN, so that not all of them are in memory at once.
+                // HSCAN returns the block data as well, which is skipped.
+                let start = Some((connection.clone(), 0u64));
+                stream::try_unfold(start, move |state| async move {
+                    let Some((mut connection, cursor)) = state else {
+                        return Ok(None);
+                    };
+                    let (cursor, entries): (u64, Vec<(i64, redis::Value)>) = redis::cmd("HSCAN")
+                        .arg(hash)
+                        .arg(cursor)
+                        .arg("COUNT")
+                        .arg(REDIS_SCAN_COUNT)
+                        .query_async(&mut connection)
+                        .await?;
+                    let positions = entries
+                        .into_iter()
+                        .map(|(key, _data)| Ok(Position::from_database_key(key)));
+                    let next = (cursor != 0).then_some((connection, cursor));
+                    Ok::<_, MapDataError>(Some((stream::iter(positions), next)))
+                })
+                .try_flatten()
+                .boxed()
+            }
+            #[cfg(feature = "experimental-leveldb")]
+            MapData::LevelDb(db) => {
+                let db = db.clone();
+                let positions = spawn_blocking(move || {
+                    let mut db = db.lock().unwrap();
+                    let mut iter = db.iter()?;
+                    let mut positions = vec![];
+                    while let Some((key, _value)) = iter.next() {
+                        match leveldb_key_to_position(key) {
+                            Some(pos) => positions.push(Ok(pos)),
+                            None => log::warn!("Skipping unexpected LevelDB key {key:?}"),
+                        }
+                    }
+                    Ok(positions)
+                })
+                .await;
                 match positions {
-                    Ok(positions) => stream::iter(
-                        positions
-                            .into_iter()
-                            .map(Position::from_database_key)
-                            .map(Ok),
-                    )
-                    .boxed(),
-                    Err(e) => stream::once(future::ready(Err(MapDataError::RedisError(e)))).boxed(),
+                    Ok(positions) => stream::iter(positions).boxed(),
+                    Err(e) => {
+                        stream::once(future::ready(Err(MapDataError::LevelDbError(e)))).boxed()
+                    }
                 }
             }
-            #[cfg(feature = "experimental-leveldb")]
-            MapData::LevelDb(db) =>
-            // TODO Use task::spawn_blocking for this, as this blocks the thread for a longer time
-            {
-                stream::iter(
-                    db.lock()
-                        .await
-                        .iter()
-                        .map_err(MapDataError::LevelDbError)?
-                        .alloc()
-                        //.inspect(|(key, _value)| println!("{key:?}"))
-                        // Now here it gets interesting. Figure out why the key's length is often 9 bytes instead of 8 bytes.
-                        .filter(|(key, _)| key.len() == 8)
-                        // And figure out why LevelDB reports corrupted blocks
-                        .map(|(key, _value)| Ok(i64::from_le_bytes(key.try_into()?)))
-                        .filter_map(|key: Result<i64, Vec<u8>>| key.ok())
-                        .map(get_integer_as_block),
-                )
-                .boxed()
+        }
+    }
+
+    /// Returns the positions of all mapblocks, sorted and without duplicates
+    ///
+    /// The positions are ordered by `x`, then `y`, then `z` (see [`Position`]'s [`Ord`]).
+    /// Postgres sorts them in the database; the other backends collect all positions
+    /// in memory before the first one is yielded.
+    pub async fn all_mapblock_positions_sorted(
+        &self,
+    ) -> BoxStream<'_, Result<Position, MapDataError>> {
+        match self {
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => sqlx::query_as(POSTGRES_POSITIONS_SORTED)
+                .fetch(pool)
+                .map_err(MapDataError::SqlError)
+                .boxed(),
+            #[allow(unreachable_patterns)]
+            _ => {
+                let positions: Result<Vec<Position>, _> =
+                    self.all_mapblock_positions().await.try_collect().await;
+                match positions {
+                    Ok(mut positions) => {
+                        positions.sort_unstable();
+                        positions.dedup();
+                        stream::iter(positions.into_iter().map(Ok)).boxed()
+                    }
+                    Err(e) => stream::once(future::ready(Err(e))).boxed(),
+                }
+            }
+        }
+    }
+
+    /// Returns the smallest cuboid containing all mapblocks, as its minimum and maximum corner
+    ///
+    /// The corners are mapblock positions. A world without any mapblock has no bounds,
+    /// which results in `None`.
+    /// Postgres computes the bounds in the database; the other backends
+    /// scan all positions via [`MapData::all_mapblock_positions`].
+    pub async fn bounds(&self) -> Result<Option<(Position, Position)>, MapDataError> {
+        match self {
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => {
+                let corners: Vec<Position> =
+                    sqlx::query_as(POSTGRES_BOUNDS).fetch_all(pool).await?;
+                // UNION ALL does not guarantee the order of the rows
+                Ok(corners.into_iter().fold(None, extend_bounds))
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                let mut positions = self.all_mapblock_positions().await;
+                let mut bounds = None;
+                while let Some(pos) = positions.try_next().await? {
+                    bounds = extend_bounds(bounds, pos);
+                }
+                Ok(bounds)
             }
         }
     }
 
+    /// Loads all mapblocks, with at most `concurrency` queries running at the same time
+    ///
+    /// A `concurrency` of 0 is treated as 1.
+    /// This is the recommended way to process a whole world.
+    /// The blocks are yielded in no particular order.
+    ///
+    /// The stream of positions occupies one connection of the pool while it runs,
+    /// so `concurrency` should be lower than the pool size
+    /// (see [`SqliteOptions::max_connections`] and [`PostgresOptions::max_connections`]).
+    /// SQLite allows only one writer at a time; writing to the same database during
+    /// the scan may fail with a busy error unless [WAL mode](`SqliteOptions::wal`) is enabled.
+    pub async fn mapblocks_buffered(
+        &self,
+        concurrency: usize,
+    ) -> BoxStream<'_, Result<(Position, MapBlock), MapDataError>> {
+        self.all_mapblock_positions()
+            .await
+            .map_ok(move |pos| async move { Ok((pos, self.get_mapblock(pos).await?)) })
+            .try_buffer_unordered(concurrency.max(1))
+            .boxed()
+    }
+
+    /// Like [`MapData::mapblocks_buffered`], but decodes the blocks on blocking threads
+    ///
+    /// Decompressing and parsing a block is pure CPU work, which limits a scan of a big world
+    /// to one core when done in the async task. Here, the raw block data is fetched with at most
+    /// `concurrency` queries running at the same time, as in [`MapData::mapblocks_buffered`],
+    /// and up to `parallelism` blocks are decoded at the same time on the runtime's
+    /// blocking thread pool. The number of available cores is a good choice for `parallelism`.
+    /// A `concurrency` or `parallelism` of 0 is treated as 1.
+    ///
+    /// The decoding does not occupy any connections, but the queries do: the stream of positions
+    /// occupies one connection of the pool while it runs, so `concurrency` should be lower than
+    /// the pool size (see [`SqliteOptions::max_connections`] and
+    /// [`PostgresOptions::max_connections`]).
+    pub async fn mapblocks_parallel(
+        &self,
+        concurrency: usize,
+        parallelism: usize,
+    ) -> BoxStream<'_, Result<(Position, MapBlock), MapDataError>> {
+        self.all_mapblock_positions()
+            .await
+            .map_ok(move |pos| async move { Ok((pos, self.get_block_data(pos).await?)) })
+            .try_buffer_unordered(concurrency.max(1))
+            .map_ok(|(pos, data)| {
+                spawn_blocking(move || match MapBlock::from_bytes(&data) {
+                    Ok(block) => Ok((pos, block)),
+                    Err(e) => Err(MapDataError::InvalidMapBlock(pos, e)),
+                })
+            })
+            .try_buffer_unordered(parallelism.max(1))
+            .boxed()
+    }
+
+    /// Tries to decode every mapblock and reports the ones that fail
+    ///
+    /// Corrupt or unsupported blocks do not abort the scan, but backend errors do.
+    /// See [`MapData::mapblocks_buffered`] for the meaning of `concurrency`.
+    pub async fn verify(&self, concurrency: usize) -> Result<VerifyReport, MapDataError> {
+        verify_blocks(self.mapblocks_buffered(concurrency).await).await
+    }
+
+    /// Like [`MapData::verify`], but decodes the blocks on blocking threads
+    ///
+    /// See [`MapData::mapblocks_parallel`] for the meaning of `concurrency` and `parallelism`.
+    pub async fn verify_parallel(
+        &self,
+        concurrency: usize,
+        parallelism: usize,
+    ) -> Result<VerifyReport, MapDataError> {
+        verify_blocks(self.mapblocks_parallel(concurrency, parallelism).await).await
+    }
+
     /// Queries the backend for the data of a single mapblock
     pub async fn get_block_data(&self, pos: Position) -> Result<Vec<u8>, MapDataError> {
-        let pos_index = pos.as_database_key();
         match self {
             #[cfg(feature = "sqlite")]
             MapData::Sqlite(pool) => sqlx::query("SELECT data FROM blocks WHERE pos = ?")
-                .bind(pos_index)
+                .bind(pos.try_as_database_key()?)
                 .fetch_one(pool)
                 .await
                 .and_then(|row| row.try_get("data"))
@@ -247,27 +706,133 @@ impl MapData {
                 .map_err(|e| MapDataError::from_sqlx_error(e, pos)),
             #[cfg(feature = "redis")]
             MapData::Redis { connection, hash } => {
-                let value: Option<_> = connection.clone().hget(hash.to_string(), pos_index).await?;
+                let value: Option<_> = connection
+                    .clone()
+                    .hget(hash.to_string(), pos.try_as_database_key()?)
+                    .await?;
                 value.ok_or(MapDataError::MapBlockNonexistent(pos))
             }
             #[cfg(feature = "experimental-leveldb")]
-            MapData::LevelDb(db) => Ok(db
-                .lock()
-                .await
-                .get(&pos_index.to_le_bytes())
-                .map_err(MapDataError::LevelDbError)?
-                .ok_or(MapDataError::MapBlockNonexistent(pos))?),
+            MapData::LevelDb(db) => {
+                let (db, key) = (db.clone(), leveldb_key(pos)?);
+                spawn_blocking(move || db.lock().unwrap().get(&key))
+                    .await
+                    .map_err(MapDataError::LevelDbError)?
+                    .ok_or(MapDataError::MapBlockNonexistent(pos))
+            }
+        }
+    }
+
+    /// Queries the backend for the data of a single mapblock and passes it to `f`
+    ///
+    /// For the SQL backends, the data is borrowed from the fetched row, which avoids
+    /// copying it into a separate buffer like [`MapData::get_block_data`] does.
+    pub async fn with_block_data<R>(
+        &self,
+        pos: Position,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => {
+                let row = sqlx::query("SELECT data FROM blocks WHERE pos = ?")
+                    .bind(pos.try_as_database_key()?)
+                    .fetch_one(pool)
+                    .await
+                    .map_err(|e| MapDataError::from_sqlx_error(e, pos))?;
+                Ok(f(row.try_get("data")?))
+            }
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => {
+                let row = sqlx::query(POSTGRES_QUERY)
+                    .bind(pos.x)
+                    .bind(pos.y)
+                    .bind(pos.z)
+                    .fetch_one(pool)
+                    .await
+                    .map_err(|e| MapDataError::from_sqlx_error(e, pos))?;
+                Ok(f(row.try_get("data")?))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Ok(f(&self.get_block_data(pos).await?)),
+        }
+    }
+
+    /// Checks whether the backend stores a map block at `pos`, without fetching it
+    pub async fn exists(&self, pos: Position) -> Result<bool, MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => Ok(sqlx::query(SQLITE_EXISTS)
+                .bind(pos.try_as_database_key()?)
+                .fetch_optional(pool)
+                .await?
+                .is_some()),
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => Ok(sqlx::query(POSTGRES_EXISTS)
+                .bind(pos.x)
+                .bind(pos.y)
+                .bind(pos.z)
+                .fetch_optional(pool)
+                .await?
+                .is_some()),
+            #[cfg(feature = "redis")]
+            MapData::Redis { connection, hash } => Ok(connection
+                .clone()
+                .hexists(hash, pos.try_as_database_key()?)
+                .await?),
+            #[cfg(feature = "experimental-leveldb")]
+            MapData::LevelDb(db) => {
+                let (db, key) = (db.clone(), leveldb_key(pos)?);
+                Ok(spawn_blocking(move || db.lock().unwrap().get(&key))
+                    .await
+                    .map_err(MapDataError::LevelDbError)?
+                    .is_some())
+            }
         }
     }
 
+    /// Checks whether all map blocks covering a region are stored in the backend
+    ///
+    /// The region is the cuboid spanned by the node positions `a` and `b`, which are both part of it.
+    /// Returns `false` as soon as one of the map blocks is missing, i.e. not yet generated.
+    /// Like [`MapData::exists`], this does not fetch the blocks.
+    pub async fn region_generated(&self, a: Position, b: Position) -> Result<bool, MapDataError> {
+        let min = Position::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)).mapblock_at();
+        let max = Position::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)).mapblock_at();
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    if !self.exists(Position::new(x, y, z)).await? {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
     /// Queries the backend for a specific map block
     ///
     /// `pos` is a map block position; this means that every dimension is divided
     /// by the side length of a map block.
+    ///
+    /// A missing block results in [`MapDataError::MapBlockNonexistent`],
+    /// a block that cannot be decoded in [`MapDataError::InvalidMapBlock`].
     pub async fn get_mapblock(&self, pos: Position) -> Result<MapBlock, MapDataError> {
-        Ok(MapBlock::from_data(
-            self.get_block_data(pos).await?.as_slice(),
-        )?)
+        self.with_block_data(pos, MapBlock::from_bytes)
+            .await?
+            .map_err(|e| MapDataError::InvalidMapBlock(pos, e))
+    }
+
+    /// Queries the backend for the timestamp of the map block at `pos`
+    ///
+    /// Only the block's header is decoded (see [`MapBlock::read_header`]), which is much cheaper
+    /// than [`MapData::get_mapblock`], e.g. for comparing the timestamps of many blocks.
+    pub async fn get_block_timestamp(&self, pos: Position) -> Result<u32, MapDataError> {
+        self.with_block_data(pos, |data| MapBlock::read_header(data))
+            .await?
+            .map(|header| header.timestamp)
+            .map_err(|e| MapDataError::InvalidMapBlock(pos, e))
     }
 
     /// Sets the backend's mapblock data for position `pos` to `data`
@@ -275,7 +840,7 @@ impl MapData {
         match self {
             #[cfg(feature = "sqlite")]
             MapData::Sqlite(pool) => sqlx::query(SQLITE_UPSERT)
-                .bind(pos.as_database_key())
+                .bind(pos.try_as_database_key()?)
                 .bind(data)
                 .execute(pool)
                 .await
@@ -294,9 +859,229 @@ impl MapData {
             #[cfg(feature = "redis")]
             MapData::Redis { connection, hash } => connection
                 .clone()
-                .hset(hash, pos.as_database_key(), data)
+                .hset(hash, pos.try_as_database_key()?, data)
+                .await
+                .map_err(|e| e.into()),
+            #[cfg(feature = "experimental-leveldb")]
+            MapData::LevelDb(db) => {
+                let (db, key) = (db.clone(), leveldb_key(pos)?);
+                let data = data.to_vec();
+                spawn_blocking(move || db.lock().unwrap().put(&key, &data))
+                    .await
+                    .map_err(MapDataError::LevelDbError)
+            }
+        }
+    }
+
+    /// Sets the backend's mapblock data for many positions at once
+    ///
+    /// For SQLite and Postgres, all blocks are written in one transaction.
+    /// If an error is returned or the future is dropped before it completes,
+    /// none of the blocks are written.
+    ///
+    /// For Redis, all blocks are sent in one pipeline, saving a round trip per block.
+    /// LevelDB writes the blocks one after another. For these two backends, writing is not atomic:
+    /// if an error is returned, some of the blocks may have been written nevertheless.
+    pub async fn set_mapblock_data_many<'a>(
+        &self,
+        blocks: impl IntoIterator<Item = (Position, &'a [u8])>,
+    ) -> Result<(), MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => {
+                let mut transaction = pool.begin().await?;
+                for (pos, data) in blocks {
+                    sqlx::query(SQLITE_UPSERT)
+                        .bind(pos.try_as_database_key()?)
+                        .bind(data)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+                Ok(transaction.commit().await?)
+            }
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => {
+                let mut transaction = pool.begin().await?;
+                for (pos, data) in blocks {
+                    sqlx::query(POSTGRES_UPSERT)
+                        .bind(pos.x)
+                        .bind(pos.y)
+                        .bind(pos.z)
+                        .bind(data)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+                Ok(transaction.commit().await?)
+            }
+            #[cfg(feature = "redis")]
+            MapData::Redis { connection, hash } => {
+                let mut pipeline = redis::pipe();
+                for (pos, data) in blocks {
+                    pipeline
+                        .hset(hash, pos.try_as_database_key()?, data)
+                        .ignore();
+                }
+                Ok(pipeline.query_async(&mut connection.clone()).await?)
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                for (pos, data) in blocks {
+                    self.set_mapblock_data(pos, data).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes the map block at `pos` from the backend
+    ///
+    /// Removing a block that does not exist is not an error.
+    pub async fn delete_mapblock(&self, pos: Position) -> Result<(), MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => sqlx::query("DELETE FROM blocks WHERE pos = ?")
+                .bind(pos.try_as_database_key()?)
+                .execute(pool)
+                .await
+                .map(|_| {})
+                .map_err(MapDataError::SqlError),
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => sqlx::query(POSTGRES_DELETE)
+                .bind(pos.x)
+                .bind(pos.y)
+                .bind(pos.z)
+                .execute(pool)
+                .await
+                .map(|_| {})
+                .map_err(MapDataError::SqlError),
+            #[cfg(feature = "redis")]
+            MapData::Redis { connection, hash } => connection
+                .clone()
+                .hdel(hash, pos.try_as_database_key()?)
                 .await
                 .map_err(|e| e.into()),
+            #[cfg(feature = "experimental-leveldb")]
+            MapData::LevelDb(db) => {
+                let (db, key) = (db.clone(), leveldb_key(pos)?);
+                spawn_blocking(move || db.lock().unwrap().delete(&key))
+                    .await
+                    .map_err(MapDataError::LevelDbError)
+            }
+        }
+    }
+
+    /// Removes all map blocks from the backend
+    ///
+    /// This is destructive and cannot be undone; the world's map will be regenerated
+    /// by Minetest when it is visited again. The database itself is kept.
+    /// Returns the number of removed blocks.
+    pub async fn clear(&self) -> Result<u64, MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => Ok(sqlx::query("DELETE FROM blocks")
+                .execute(pool)
+                .await?
+                .rows_affected()),
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => Ok(sqlx::query("DELETE FROM blocks")
+                .execute(pool)
+                .await?
+                .rows_affected()),
+            #[cfg(feature = "redis")]
+            MapData::Redis { connection, hash } => {
+                let (count, _deleted): (u64, u64) = redis::pipe()
+                    .atomic()
+                    .hlen(hash)
+                    .del(hash)
+                    .query_async(&mut connection.clone())
+                    .await?;
+                Ok(count)
+            }
+            #[cfg(feature = "experimental-leveldb")]
+            MapData::LevelDb(db) => {
+                let db = db.clone();
+                spawn_blocking(move || {
+                    let mut db = db.lock().unwrap();
+                    let mut keys = vec![];
+                    let mut iter = db.iter()?;
+                    while let Some((key, _value)) = iter.next() {
+                        keys.push(key.to_vec());
+                    }
+                    drop(iter);
+                    for key in &keys {
+                        db.delete(key)?;
+                    }
+                    Ok(keys.len() as u64)
+                })
+                .await
+                .map_err(MapDataError::LevelDbError)
+            }
+        }
+    }
+
+    /// Copies all map blocks into another map data backend
+    ///
+    /// The raw block data is copied without decoding it, so this also works for
+    /// blocks in formats this crate cannot parse. Existing blocks in `dest` are replaced.
+    /// Returns the number of copied blocks.
+    ///
+    /// The positions are collected before copying, so the source database
+    /// is not read from and written to at the same time.
+    pub async fn copy_to(&self, dest: &MapData) -> Result<u64, MapDataError> {
+        let positions: Vec<_> = self.all_mapblock_positions().await.try_collect().await?;
+        let mut count = 0;
+        for pos in positions {
+            let data = self.get_block_data(pos).await?;
+            dest.set_mapblock_data(pos, &data).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Reclaims unused space and refreshes the query planner statistics
+    ///
+    /// For SQLite, this runs `VACUUM` and `PRAGMA optimize`, which shrinks the database file
+    /// after many blocks have been removed. The database must not be opened read-only.
+    /// For the other backends, this does nothing.
+    pub async fn optimize(&self) -> Result<(), MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => {
+                sqlx::query("VACUUM").execute(pool).await?;
+                sqlx::query("PRAGMA optimize").execute(pool).await?;
+                Ok(())
+            }
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether the backend is reachable
+    ///
+    /// This runs `SELECT 1` for SQLite and Postgres and sends a `PING` to Redis.
+    /// Long-running services can use it to detect a dropped connection before a query fails.
+    /// For LevelDB, which is no server, this does nothing.
+    pub async fn ping(&self) -> Result<(), MapDataError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            MapData::Sqlite(pool) => {
+                sqlx::query("SELECT 1").execute(pool).await?;
+                Ok(())
+            }
+            #[cfg(feature = "postgres")]
+            MapData::Postgres(pool) => {
+                sqlx::query("SELECT 1").execute(pool).await?;
+                Ok(())
+            }
+            #[cfg(feature = "redis")]
+            MapData::Redis { connection, .. } => {
+                redis::cmd("PING")
+                    .query_async::<_, ()>(&mut connection.clone())
+                    .await?;
+                Ok(())
+            }
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
         }
     }
 
@@ -305,14 +1090,137 @@ impl MapData {
         self.set_mapblock_data(pos, &block.to_binary()?).await
     }
 
+    /// Like [`MapData::set_mapblock`], but verifies the write
+    ///
+    /// Before writing, the database key of `pos` has to map back to `pos`.
+    /// After writing, the block data is read back from `pos` and compared with the written data.
+    /// A mismatch results in [`MapDataError::WriteVerificationFailed`].
+    /// This costs an additional query per block.
+    pub async fn set_mapblock_checked(
+        &self,
+        pos: Position,
+        block: &MapBlock,
+    ) -> Result<(), MapDataError> {
+        if Position::from_database_key(pos.try_as_database_key()?) != pos {
+            return Err(MapDataError::WriteVerificationFailed(pos));
+        }
+        let data = block.to_binary()?;
+        self.set_mapblock_data(pos, &data).await?;
+        let matches = self
+            .with_block_data(pos, |written| written == data.as_slice())
+            .await?;
+        if matches {
+            Ok(())
+        } else {
+            Err(MapDataError::WriteVerificationFailed(pos))
+        }
+    }
+
+    /// Gets the node at the given world position, without caching its map block
+    ///
+    /// The containing map block is fetched and decoded on each call, so use a
+    /// [`VoxelManip`](`crate::VoxelManip`) to read many nodes of the same block.
+    /// Like [`VoxelManip::get_node`](`crate::VoxelManip::get_node`), a node in a
+    /// map block that does not exist is an [ignore](`crate::map_block::CONTENT_IGNORE`) node.
+    pub async fn get_node(&self, pos: Position) -> Result<Node, MapDataError> {
+        let (blockpos, nodepos) = pos.split_at_block();
+        match self.get_mapblock(blockpos).await {
+            Ok(mapblock) => Ok(mapblock.get_node_at(nodepos)),
+            Err(MapDataError::MapBlockNonexistent(_)) => {
+                Ok(MapBlock::unloaded().get_node_at(nodepos))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Enumerate all nodes from the mapblock at `pos`
     ///
-    /// Yields all nodes along with their relative position within the map block
+    /// Yields all nodes along with their world position
     pub async fn iter_mapblock_nodes(
         &self,
         mapblock_pos: Position,
-    ) -> Result<impl Iterator<Item = (Position, Node)>, MapDataError> {
+    ) -> Result<NodeIter, MapDataError> {
         let mapblock = self.get_mapblock(mapblock_pos).await?;
         Ok(NodeIter::from(mapblock, mapblock_pos))
     }
+
+    /// Enumerate all nodes of the world
+    ///
+    /// Yields all nodes along with their world position.
+    /// The nodes are ordered by map block, in the order of [`MapData::all_mapblock_positions`],
+    /// and within a block by node index (see [`Position::from_node_index`]).
+    pub async fn iter_all_nodes(&self) -> BoxStream<'_, Result<(Position, Node), MapDataError>> {
+        self.all_mapblock_positions()
+            .await
+            .and_then(move |pos| self.iter_mapblock_nodes(pos))
+            .map_ok(|nodes| stream::iter(nodes.map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Writes all nodes in the cuboid spanned by the node positions `a` and `b` as CSV
+    ///
+    /// Both corners are part of the region. After a header line, there is one
+    /// `x,y,z,content,param1,param2` row per node, with the content as UTF-8 string.
+    /// Air nodes are skipped unless `include_air` is set, and map blocks that
+    /// were not generated yet are skipped entirely.
+    /// Returns the number of rows written, without the header.
+    ///
+    /// `writer` is a [`futures::io::AsyncWrite`]; with tokio, wrap it using `tokio_util::compat`.
+    pub async fn dump_nodes_csv(
+        &self,
+        a: Position,
+        b: Position,
+        include_air: bool,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<u64, MapDataError> {
+        writer.write_all(b"x,y,z,content,param1,param2\n").await?;
+        let mut rows = 0;
+        for (blockpos, min, max) in Position::split_region_into_blocks(a, b) {
+            let mapblock = match self.get_mapblock(blockpos).await {
+                Ok(mapblock) => mapblock,
+                Err(MapDataError::MapBlockNonexistent(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let block_min = blockpos * MAPBLOCK_LENGTH as i16;
+            let mut csv = String::new();
+            for (pos, node) in mapblock.iter_region(min, max) {
+                if !include_air && node.param0 == CONTENT_AIR {
+                    continue;
+                }
+                let Position { x, y, z } = block_min + pos;
+                let content = String::from_utf8_lossy(node.param0);
+                let content = csv_field(&content);
+                let (param1, param2) = (node.param1, node.param2);
+                csv.push_str(&format!("{x},{y},{z},{content},{param1},{param2}\n"));
+                rows += 1;
+            }
+            writer.write_all(csv.as_bytes()).await?;
+        }
+        writer.flush().await?;
+        Ok(rows)
+    }
+}
+
+/// Grows the cuboid given by its minimum and maximum corner, so that it contains `pos`
+fn extend_bounds(
+    bounds: Option<(Position, Position)>,
+    pos: Position,
+) -> Option<(Position, Position)> {
+    Some(match bounds {
+        None => (pos, pos),
+        Some((min, max)) => (
+            Position::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z)),
+            Position::new(max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z)),
+        ),
+    })
+}
+
+/// Quotes a CSV field if it contains characters that would break the row
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
 }
@@ -0,0 +1,197 @@
+//! Contains the [`Player`] type to read the player data of a world
+
+use crate::map_block::read_inventory;
+use crate::runtime as fs;
+use crate::world::WorldError;
+#[cfg(feature = "sqlite")]
+use crate::MapDataError;
+#[cfg(feature = "sqlite")]
+use log::LevelFilter;
+#[cfg(feature = "sqlite")]
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+#[cfg(feature = "sqlite")]
+use sqlx::{prelude::*, ConnectOptions};
+#[cfg(feature = "sqlite")]
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Player positions are stored in units of 1/10 node
+const BS: f32 = 10.0;
+
+/// A player that has joined the world at least once
+#[derive(Debug, Clone)]
+pub struct Player {
+    /// The player's name
+    pub name: String,
+    /// The player's position in node units
+    pub position: (f32, f32, f32),
+    /// The player's health points
+    pub hp: u16,
+    /// Serialized inventory
+    pub inventory: Vec<u8>,
+}
+
+fn malformed(description: impl Into<String>) -> WorldError {
+    WorldError::PlayerDataMalformed(description.into())
+}
+
+fn parse_position(value: &str) -> Result<(f32, f32, f32), WorldError> {
+    let coords: Vec<f32> = value
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|c| c.trim().parse::<f32>().map(|c| c / BS))
+        .collect::<Result<_, _>>()
+        .map_err(|e| malformed(format!("position '{value}': {e}")))?;
+    match coords[..] {
+        [x, y, z] => Ok((x, y, z)),
+        _ => Err(malformed(format!(
+            "position '{value}' does not have 3 components"
+        ))),
+    }
+}
+
+/// Parses the content of a file in the `players` directory
+pub(crate) fn parse_player_file(mut data: &[u8]) -> Result<Player, WorldError> {
+    let mut name = None;
+    let mut position = None;
+    let mut hp = None;
+    loop {
+        let line_end = data
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| malformed("PlayerArgsEnd is missing"))?;
+        let line = std::str::from_utf8(&data[..line_end])
+            .map_err(|_| malformed("player args are not valid UTF-8"))?
+            .trim_end();
+        data = &data[line_end + 1..];
+        if line == "PlayerArgsEnd" {
+            break;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = Some(String::from(value)),
+                "position" => position = Some(parse_position(value)?),
+                "hp" => hp = Some(value.parse()?),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Player {
+        name: name.ok_or_else(|| malformed("name is missing"))?,
+        position: position.ok_or_else(|| malformed("position is missing"))?,
+        hp: hp.ok_or_else(|| malformed("hp is missing"))?,
+        inventory: read_inventory(&mut data).map_err(|e| malformed(e.to_string()))?,
+    })
+}
+
+/// Reads all players from the files backend, sorted by name
+pub(crate) async fn read_player_files(directory: &Path) -> Result<Vec<Player>, WorldError> {
+    let entries = match fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        // No player has joined the world yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut players = vec![];
+    for entry in entries {
+        if fs::metadata(&entry).await?.is_file() {
+            players.push(parse_player_file(&fs::read(&entry).await?)?);
+        }
+    }
+    players.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(players)
+}
+
+/// Assembles the serialized inventory of a player from the sqlite tables
+#[cfg(feature = "sqlite")]
+async fn read_sqlite_inventory(pool: &SqlitePool, player: &str) -> Result<Vec<u8>, sqlx::Error> {
+    let lists = sqlx::query(
+        "SELECT inv_id, inv_width, inv_name, inv_size FROM player_inventories
+ WHERE player = ? ORDER BY inv_id",
+    )
+    .bind(player)
+    .fetch_all(pool)
+    .await?;
+
+    let mut inventory = String::new();
+    for list in lists {
+        let inv_id: i64 = list.try_get("inv_id")?;
+        let width: i64 = list.try_get("inv_width")?;
+        let list_name: String = list.try_get("inv_name")?;
+        let size: i64 = list.try_get("inv_size")?;
+        let items: HashMap<i64, String> = sqlx::query(
+            "SELECT slot_id, item FROM player_inventory_items WHERE player = ? AND inv_id = ?",
+        )
+        .bind(player)
+        .bind(inv_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok((row.try_get("slot_id")?, row.try_get("item")?)))
+        .collect::<Result<_, sqlx::Error>>()?;
+
+        inventory.push_str(&format!("List {list_name} {size}\nWidth {width}\n"));
+        for slot in 0..size {
+            match items.get(&slot).filter(|item| !item.is_empty()) {
+                Some(item) => inventory.push_str(&format!("Item {item}\n")),
+                None => inventory.push_str("Empty\n"),
+            }
+        }
+        inventory.push_str("EndInventoryList\n");
+    }
+    inventory.push_str("EndInventory\n");
+    Ok(inventory.into_bytes())
+}
+
+/// Reads all players from a `players.sqlite` database, sorted by name
+#[cfg(feature = "sqlite")]
+pub(crate) async fn read_players_sqlite(filename: &Path) -> Result<Vec<Player>, WorldError> {
+    if let Err(e) = fs::metadata(filename).await {
+        // Minetest creates the database once the first player joins
+        return match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(vec![]),
+            _ => Err(e.into()),
+        };
+    }
+    let opts = SqliteConnectOptions::new()
+        .filename(filename)
+        .read_only(true)
+        .log_statements(LevelFilter::Debug);
+    let pool = SqlitePool::connect_with(opts)
+        .await
+        .map_err(MapDataError::SqlError)?;
+    let rows = sqlx::query(
+        "SELECT name, CAST(posX AS REAL) AS x, CAST(posY AS REAL) AS y,
+ CAST(posZ AS REAL) AS z, hp FROM player ORDER BY name",
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(MapDataError::SqlError)?;
+
+    let mut players = Vec::with_capacity(rows.len());
+    for row in rows {
+        players.push(
+            player_from_row(&pool, row)
+                .await
+                .map_err(MapDataError::SqlError)?,
+        );
+    }
+    Ok(players)
+}
+
+#[cfg(feature = "sqlite")]
+async fn player_from_row(pool: &SqlitePool, row: SqliteRow) -> Result<Player, sqlx::Error> {
+    let name: String = row.try_get("name")?;
+    let x: f64 = row.try_get("x")?;
+    let y: f64 = row.try_get("y")?;
+    let z: f64 = row.try_get("z")?;
+    Ok(Player {
+        position: (x as f32 / BS, y as f32 / BS, z as f32 / BS),
+        hp: row.try_get("hp")?,
+        inventory: read_sqlite_inventory(pool, &name).await?,
+        name,
+    })
+}
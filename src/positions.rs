@@ -8,18 +8,28 @@ use sqlx::postgres::PgRow;
 use sqlx::sqlite::SqliteRow;
 #[cfg(any(feature = "sqlite", feature = "postgres"))]
 use sqlx::{FromRow, Row};
-use std::io;
 use std::ops::{Add, Rem};
 
+/// A position is outside of the range it has to be converted to
+///
+/// [`Position::try_as_database_key`] needs each component of the mapblock position to be in
+/// `-2048..=2047`, the conversion from `(i32, i32, i32)` needs each component to fit in an `i16`.
+/// The offending components are kept as they were given.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Position {0:?} is out of range")]
+pub struct PositionRangeError(pub (i32, i32, i32));
+
 /// A point location within a Minetest world
 ///
 /// This type is used for addressing one of the following:
 /// * voxels ([nodes](`crate::Node`), node timers, metadata, ...).
 /// * [MapBlocks](`crate::MapBlock`). In this case, all three dimensions are divided by the
-/// MapBlock [side length](`crate::MAPBLOCK_LENGTH`).
+///   MapBlock [side length](`crate::MAPBLOCK_LENGTH`).
 ///
 /// A voxel position may either be absolute or relative to a mapblock root.
-#[derive(Debug, PartialEq, Copy, Clone, Eq, Hash)]
+///
+/// Positions are ordered by `x`, then `y`, then `z`.
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Hash, PartialOrd, Ord)]
 pub struct Position {
     /// "East direction". The direction in which the sun rises.
     pub x: i16,
@@ -65,11 +75,89 @@ impl std::ops::Mul<i16> for Position {
     }
 }
 
+impl std::ops::AddAssign for Position {
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// let mut pos = Position::new(1i16, 2, 3);
+    /// pos += Position::new(10i16, 20, 30);
+    /// assert_eq!(pos, Position::new(11i16, 22, 33));
+    /// ```
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl std::ops::SubAssign for Position {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl std::ops::MulAssign<i16> for Position {
+    fn mul_assign(&mut self, rhs: i16) {
+        *self = *self * rhs;
+    }
+}
+
+impl From<(i16, i16, i16)> for Position {
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// let pos: Position = (1, -2, 3).into();
+    /// assert_eq!(pos, Position::new(1i16, -2, 3));
+    /// assert_eq!(<(i16, i16, i16)>::from(pos), (1, -2, 3));
+    /// ```
+    fn from((x, y, z): (i16, i16, i16)) -> Self {
+        Position { x, y, z }
+    }
+}
+
+impl From<Position> for (i16, i16, i16) {
+    fn from(pos: Position) -> Self {
+        (pos.x, pos.y, pos.z)
+    }
+}
+
+impl TryFrom<(i32, i32, i32)> for Position {
+    type Error = PositionRangeError;
+
+    /// Fails if one of the components does not fit in an i16
+    ///
+    /// ```
+    /// use minetestworld::positions::PositionRangeError;
+    /// use minetestworld::Position;
+    ///
+    /// let pos = Position::try_from((1, -2, 3)).unwrap();
+    /// assert_eq!(<(i32, i32, i32)>::from(pos), (1, -2, 3));
+    /// assert_eq!(
+    ///     Position::try_from((40000, 0, 0)),
+    ///     Err(PositionRangeError((40000, 0, 0)))
+    /// );
+    /// ```
+    fn try_from(components: (i32, i32, i32)) -> Result<Self, Self::Error> {
+        let (x, y, z) = components;
+        let error = |_| PositionRangeError(components);
+        Ok(Position {
+            x: x.try_into().map_err(error)?,
+            y: y.try_into().map_err(error)?,
+            z: z.try_into().map_err(error)?,
+        })
+    }
+}
+
+impl From<Position> for (i32, i32, i32) {
+    fn from(pos: Position) -> Self {
+        (pos.x.into(), pos.y.into(), pos.z.into())
+    }
+}
+
+#[cfg(feature = "postgres")]
 fn invalid_data_error<E>(error: E) -> sqlx::Error
 where
     E: Into<Box<dyn std::error::Error + Send + Sync>>,
 {
-    sqlx::Error::Io(io::Error::new(io::ErrorKind::InvalidData, error))
+    sqlx::Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, error))
 }
 
 #[cfg(feature = "sqlite")]
@@ -86,11 +174,7 @@ impl FromRow<'_, PgRow> for Position {
         let x: i32 = row.try_get("posx")?;
         let y: i32 = row.try_get("posy")?;
         let z: i32 = row.try_get("posz")?;
-        Ok(Position {
-            x: x.try_into().map_err(invalid_data_error)?,
-            y: y.try_into().map_err(invalid_data_error)?,
-            z: z.try_into().map_err(invalid_data_error)?,
-        })
+        Position::try_from((x, y, z)).map_err(invalid_data_error)
     }
 }
 
@@ -102,7 +186,56 @@ where
     (a % b + b) % b
 }
 
+/// Number of nodes in the cuboid spanned by the corners `a` and `b`, both included
+///
+/// ```
+/// use minetestworld::positions::node_count;
+/// use minetestworld::Position;
+///
+/// assert_eq!(node_count(Position::new(0i16, 0, 0), Position::new(15i16, 15, 15)), 4096);
+/// assert_eq!(node_count(Position::splat(i16::MAX), Position::splat(i16::MIN)), 1 << 48);
+/// ```
+pub fn node_count(a: Position, b: Position) -> u64 {
+    let extent = |a: i16, b: i16| (a as i64 - b as i64).unsigned_abs() + 1;
+    extent(a.x, b.x) * extent(a.y, b.y) * extent(a.z, b.z)
+}
+
+/// Number of map blocks overlapping the cuboid spanned by the node positions `a` and `b`
+///
+/// These are the blocks yielded by [`Position::split_region_into_blocks`].
+///
+/// ```
+/// use minetestworld::positions::block_count;
+/// use minetestworld::Position;
+///
+/// assert_eq!(block_count(Position::new(14i16, 0, 0), Position::new(17i16, 0, 0)), 2);
+/// ```
+pub fn block_count(a: Position, b: Position) -> u64 {
+    node_count(a.mapblock_at(), b.mapblock_at())
+}
+
 impl Position {
+    /// The position with all components being zero
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// assert_eq!(Position::ORIGIN + Position::Y, Position::new(0i16, 1, 0));
+    /// assert_eq!(Position::X * 3 + Position::Z, Position::new(3i16, 0, 1));
+    /// ```
+    pub const ORIGIN: Position = Position::splat(0);
+    /// Unit vector in x direction
+    pub const X: Position = Position { x: 1, y: 0, z: 0 };
+    /// Unit vector in y direction
+    pub const Y: Position = Position { x: 0, y: 1, z: 0 };
+    /// Unit vector in z direction
+    pub const Z: Position = Position { x: 0, y: 0, z: 1 };
+
+    /// Create a position with all components set to `v`
+    pub const fn splat(v: i16) -> Self {
+        Position { x: v, y: v, z: v }
+    }
+
     /// Create a new position value from its components
     pub fn new<I: Into<i16>>(x: I, y: I, z: I) -> Self {
         Position {
@@ -112,8 +245,99 @@ impl Position {
         }
     }
 
+    /// Component-wise addition, returning `None` if any component overflows
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// let edge = Position::new(i16::MAX, 0, 0);
+    /// assert_eq!(edge.checked_add(Position::new(1i16, 0, 0)), None);
+    /// ```
+    pub fn checked_add(self, rhs: Position) -> Option<Position> {
+        Some(Position {
+            x: self.x.checked_add(rhs.x)?,
+            y: self.y.checked_add(rhs.y)?,
+            z: self.z.checked_add(rhs.z)?,
+        })
+    }
+
+    /// Component-wise subtraction, returning `None` if any component overflows
+    pub fn checked_sub(self, rhs: Position) -> Option<Position> {
+        Some(Position {
+            x: self.x.checked_sub(rhs.x)?,
+            y: self.y.checked_sub(rhs.y)?,
+            z: self.z.checked_sub(rhs.z)?,
+        })
+    }
+
+    /// Scalar multiplication, returning `None` if any component overflows
+    pub fn checked_mul(self, rhs: i16) -> Option<Position> {
+        Some(Position {
+            x: self.x.checked_mul(rhs)?,
+            y: self.y.checked_mul(rhs)?,
+            z: self.z.checked_mul(rhs)?,
+        })
+    }
+
+    /// Component-wise addition, clamping each component to the bounds of `i16`
+    pub fn saturating_add(self, rhs: Position) -> Position {
+        Position {
+            x: self.x.saturating_add(rhs.x),
+            y: self.y.saturating_add(rhs.y),
+            z: self.z.saturating_add(rhs.z),
+        }
+    }
+
+    /// Component-wise subtraction, clamping each component to the bounds of `i16`
+    pub fn saturating_sub(self, rhs: Position) -> Position {
+        Position {
+            x: self.x.saturating_sub(rhs.x),
+            y: self.y.saturating_sub(rhs.y),
+            z: self.z.saturating_sub(rhs.z),
+        }
+    }
+
+    /// Scalar multiplication, clamping each component to the bounds of `i16`
+    pub fn saturating_mul(self, rhs: i16) -> Position {
+        Position {
+            x: self.x.saturating_mul(rhs),
+            y: self.y.saturating_mul(rhs),
+            z: self.z.saturating_mul(rhs),
+        }
+    }
+
     /// Convert a mapblock database index into coordinates
+    #[cfg_attr(
+        not(any(
+            feature = "sqlite",
+            feature = "postgres",
+            feature = "redis",
+            feature = "experimental-leveldb"
+        )),
+        allow(dead_code)
+    )]
     pub(crate) fn from_database_key(i: i64) -> Position {
+        Position::from(Position::mapblock_key_components(i))
+    }
+
+    /// Splits a mapblock database key into its x, y, and z components
+    ///
+    /// The key packs 12 bits per component, which are taken in the order x, y, z
+    /// by repeatedly taking the remainder modulo 4096. Values from 2048 up denote negative
+    /// components, so 4095 is -1. As a component is subtracted before the next one is
+    /// extracted, a negative component borrows from the following ones.
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// // 8 + 13 * 4096 + 8 * 4096²
+    /// assert_eq!(Position::mapblock_key_components(134270984), (8, 13, 8));
+    /// // 2 + 0 * 4096 + (-11) * 4096²
+    /// assert_eq!(Position::mapblock_key_components(-184549374), (2, 0, -11));
+    /// // -1 + (-1) * 4096 + (-1) * 4096²
+    /// assert_eq!(Position::mapblock_key_components(-16781313), (-1, -1, -1));
+    /// ```
+    pub fn mapblock_key_components(key: i64) -> (i16, i16, i16) {
         fn unsigned_to_signed(i: i64, max_positive: i64) -> i64 {
             if i < max_positive {
                 i
@@ -122,29 +346,75 @@ impl Position {
             }
         }
 
-        let x = unsigned_to_signed(modulo(i, 4096), 2048) as i16;
-        let mut i = (i - x as i64) / 4096;
+        let x = unsigned_to_signed(modulo(key, 4096), 2048) as i16;
+        let mut i = (key - x as i64) / 4096;
         let y = unsigned_to_signed(modulo(i, 4096), 2048) as i16;
         i = (i - y as i64) / 4096;
         let z = unsigned_to_signed(modulo(i, 4096), 2048) as i16;
-        Position { x, y, z }
+        (x, y, z)
+    }
+
+    /// Packs x, y, and z components into a mapblock database key
+    ///
+    /// This is the inverse of [`Position::mapblock_key_components`]: `x + y * 4096 + z * 4096²`.
+    /// Each component has to be in `-2048..=2047`, otherwise the key aliases other components.
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// assert_eq!(Position::mapblock_key_from_components((8, 13, 8)), 134270984);
+    /// assert_eq!(Position::mapblock_key_from_components((2, 0, -11)), -184549374);
+    /// assert_eq!(Position::mapblock_key_from_components((-1, -1, -1)), -16781313);
+    /// ```
+    pub fn mapblock_key_from_components((x, y, z): (i16, i16, i16)) -> i64 {
+        x as i64 + y as i64 * 4096 + z as i64 * 16777216
+    }
+
+    /// Convert a map block position to an integer, if it is in the storable range
+    ///
+    /// This integer is used as primary key in the sqlite, redis, and leveldb backends.
+    /// It packs 12 bits per component, so each component has to be in `-2048..=2047`.
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// assert_eq!(Position::new(1i16, 2, 3).try_as_database_key(), Ok(1 + 2 * 4096 + 3 * 16777216));
+    /// assert!(Position::new(2048i16, 0, 0).try_as_database_key().is_err());
+    /// ```
+    pub fn try_as_database_key(&self) -> Result<i64, PositionRangeError> {
+        let in_range = |c: i16| (-2048..=2047).contains(&c);
+        if in_range(self.x) && in_range(self.y) && in_range(self.z) {
+            Ok(self.as_database_key())
+        } else {
+            Err(PositionRangeError((*self).into()))
+        }
     }
 
     /// Convert a map block position to an integer
     ///
     /// This integer is used as primary key in the sqlite and redis backends.
+    /// Each component has to be in `-2048..=2047`, otherwise the key aliases another position.
+    /// See [`Position::try_as_database_key`] for a checked variant.
     pub(crate) fn as_database_key(&self) -> i64 {
-        self.x as i64 + self.y as i64 * 4096 + self.z as i64 * 16777216
+        Position::mapblock_key_from_components((*self).into())
     }
 
-    /// Convert a nodex index (used in flat 16·16·16 arrays) into a node position
+    /// Convert a node index (used in flat 16·16·16 arrays) into a node position
     ///
     /// The node position will be relative to the map block.
-    pub(crate) fn from_node_index(node_index: u16) -> Position {
+    /// Indices are packed with x varying fastest: `x + 16*y + 256*z`.
+    /// Valid indices are `0..4096`; higher bits are ignored.
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// assert_eq!(Position::from_node_index(1 + 16 * 2 + 256 * 3), Position::new(1i16, 2, 3));
+    /// ```
+    pub fn from_node_index(node_index: u16) -> Position {
         let x = node_index % 16;
         let i = node_index / 16;
         let y = i % 16;
-        let i = node_index / 16;
+        let i = i / 16;
         let z = i % 16;
         Position {
             x: x as i16,
@@ -154,7 +424,10 @@ impl Position {
     }
 
     /// Convert a MapBlock-relative node position into a flat array index
-    pub(crate) fn as_node_index(&self) -> u16 {
+    ///
+    /// This is the inverse of [`Position::from_node_index`].
+    /// The components have to be in `0..16`, otherwise the result is meaningless.
+    pub fn as_node_index(&self) -> u16 {
         self.x as u16 + 16 * self.y as u16 + 256 * self.z as u16
     }
 
@@ -168,9 +441,70 @@ impl Position {
     }
 
     /// Split this node position into a mapblock position and a relative node position
+    ///
+    /// Every component of the relative node position is in `0..`[`MAPBLOCK_LENGTH`]
+    /// for all node positions, including negative ones,
+    /// so it can be passed to [`Position::as_node_index`] as is.
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// let (block, offset) = Position::new(-1i16, 17, 0).split_at_block();
+    /// assert_eq!(block, Position::new(-1i16, 1, 0));
+    /// assert_eq!(offset, Position::new(15i16, 1, 0));
+    /// ```
     pub fn split_at_block(&self) -> (Position, Position) {
         let blockpos = self.mapblock_at();
         let relative_pos = *self - blockpos * MAPBLOCK_LENGTH as i16;
         (blockpos, relative_pos)
     }
+
+    /// Splits the cuboid spanned by the node positions `a` and `b` at map block boundaries
+    ///
+    /// Both corners are part of the region. For each covered map block, the iterator yields
+    /// the mapblock position and the minimum and maximum block-relative node positions
+    /// of the part of the region inside of that block.
+    ///
+    /// ```
+    /// use minetestworld::Position;
+    ///
+    /// let parts: Vec<_> = Position::split_region_into_blocks(
+    ///     Position::new(14i16, 0, 0),
+    ///     Position::new(17i16, 0, 0),
+    /// ).collect();
+    /// assert_eq!(parts, vec![
+    ///     (Position::new(0i16, 0, 0), Position::new(14i16, 0, 0), Position::new(15i16, 0, 0)),
+    ///     (Position::new(1i16, 0, 0), Position::new(0i16, 0, 0), Position::new(1i16, 0, 0)),
+    /// ]);
+    /// ```
+    pub fn split_region_into_blocks(
+        a: Position,
+        b: Position,
+    ) -> impl Iterator<Item = (Position, Position, Position)> {
+        let (min_block, min_offset) =
+            Position::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)).split_at_block();
+        let (max_block, max_offset) =
+            Position::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)).split_at_block();
+        let last = MAPBLOCK_LENGTH as i16 - 1;
+        // The offset range along one axis, for the block with coordinate `block` on that axis
+        let range = move |block: i16, min_block: i16, min: i16, max_block: i16, max: i16| {
+            let from = if block == min_block { min } else { 0 };
+            let to = if block == max_block { max } else { last };
+            (from, to)
+        };
+        (min_block.z..=max_block.z).flat_map(move |bz| {
+            (min_block.y..=max_block.y).flat_map(move |by| {
+                (min_block.x..=max_block.x).map(move |bx| {
+                    let (x0, x1) = range(bx, min_block.x, min_offset.x, max_block.x, max_offset.x);
+                    let (y0, y1) = range(by, min_block.y, min_offset.y, max_block.y, max_offset.y);
+                    let (z0, z1) = range(bz, min_block.z, min_offset.z, max_block.z, max_offset.z);
+                    (
+                        Position::new(bx, by, bz),
+                        Position::new(x0, y0, z0),
+                        Position::new(x1, y1, z1),
+                    )
+                })
+            })
+        })
+    }
 }
@@ -0,0 +1,81 @@
+//! Abstracts over the file system functions of the selected async runtime
+
+#[cfg(all(feature = "async-std", feature = "tokio"))]
+compile_error!("The features `async-std` and `tokio` are mutually exclusive");
+
+#[cfg(not(any(feature = "async-std", feature = "tokio")))]
+compile_error!("Either the `async-std` or the `tokio` feature has to be enabled");
+
+#[cfg(feature = "async-std")]
+pub(crate) use async_std::fs::{create_dir_all, metadata, read, read_to_string, write};
+#[cfg(feature = "tokio")]
+pub(crate) use tokio::fs::{create_dir_all, metadata, read, read_to_string, write};
+
+use std::path::{Path, PathBuf};
+
+/// Returns the paths of all entries in a directory
+#[cfg(feature = "async-std")]
+pub(crate) async fn read_dir(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    use futures::TryStreamExt;
+    async_std::fs::read_dir(path)
+        .await?
+        .map_ok(|entry| entry.path().into())
+        .try_collect()
+        .await
+}
+
+/// Returns the paths of all entries in a directory
+#[cfg(feature = "tokio")]
+pub(crate) async fn read_dir(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = tokio::fs::read_dir(path).await?;
+    let mut paths = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        paths.push(entry.path());
+    }
+    Ok(paths)
+}
+
+/// Runs a future to completion on the current thread
+#[cfg(all(feature = "blocking", feature = "async-std"))]
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    async_std::task::block_on(future)
+}
+
+/// Runs a future to completion on the current thread
+///
+/// All calls share one runtime, since connection pools are bound to the runtime
+/// they were created in.
+#[cfg(all(feature = "blocking", feature = "tokio"))]
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create the tokio runtime")
+        })
+        .block_on(future)
+}
+
+/// Runs a blocking operation on a thread where blocking is acceptable
+#[cfg(feature = "async-std")]
+pub(crate) async fn spawn_blocking<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    async_std::task::spawn_blocking(f).await
+}
+
+/// Runs a blocking operation on a thread where blocking is acceptable
+#[cfg(feature = "tokio")]
+pub(crate) async fn spawn_blocking<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
@@ -1,11 +1,40 @@
-use crate::positions::Position;
+use crate::map_block::{
+    facedir_rotation, split_itemstring, MapBlockError, NodeMetadata, NodeTimer, NodeVar,
+    OutOfBlockError, StaticObject,
+};
+#[cfg(feature = "postgres")]
+use crate::map_data::check_postgres_columns;
+use crate::map_data::SqliteOptions;
+use crate::positions::{Position, PositionRangeError};
 use crate::world::keyvalue_to_uri_connectionstr;
+use crate::world::parse_settings;
+use crate::world::supported_backends;
+use crate::world::Backend;
 use crate::MapBlock;
 use crate::MapData;
 use crate::MapDataError;
+use crate::Node;
+use crate::VoxelManip;
 use crate::World;
 use futures::prelude::*;
 
+// Run the tests on the runtime the crate is built for
+#[cfg(feature = "async-std")]
+use async_std::test as async_test;
+#[cfg(feature = "tokio")]
+use tokio::test as async_test;
+
+/// Creates an empty directory for a test to write into
+///
+/// It lives in the system's temporary directory and is removed when dropped,
+/// also if the test fails.
+fn scratch_dir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("minetestworld-")
+        .tempdir()
+        .unwrap()
+}
+
 #[test]
 fn simple_math() {
     assert_eq!(
@@ -18,14 +47,14 @@ fn simple_math() {
     );
 }
 
-#[async_std::test]
+#[async_test]
 async fn db_exists() {
     MapData::from_sqlite_file("TestWorld/map.sqlite", true)
         .await
         .unwrap();
 }
 
-#[async_std::test]
+#[async_test]
 async fn can_query() {
     let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
         .await
@@ -42,7 +71,155 @@ async fn can_query() {
     assert_eq!(block.len(), 40);
 }
 
-#[async_std::test]
+#[async_test]
+async fn mapdata_get_node() {
+    let world = World::open("TestWorld").await.unwrap();
+    let mapdata = world.get_map_data().await.unwrap();
+    let mut vm = VoxelManip::new(world.get_map_data().await.unwrap());
+    for pos in [
+        Position::new(-208i16, -128, 32),
+        Position::new(-200i16, -120, 40),
+        Position::new(0i16, 30000, 0),
+    ] {
+        assert_eq!(
+            mapdata.get_node(pos).await.unwrap(),
+            vm.get_node(pos).await.unwrap()
+        );
+    }
+    assert!(mapdata
+        .get_node(Position::new(0i16, 30000, 0))
+        .await
+        .unwrap()
+        .is_ignore());
+    vm.discard_changes();
+}
+
+#[async_test]
+async fn region_generated() {
+    let world = World::open("TestWorld").await.unwrap();
+    let vm = VoxelManip::new(world.get_map_data().await.unwrap());
+    // Block (-13, -8, 2) and its neighbour in +x direction exist, the one in -x direction does not
+    let (a, b) = (
+        Position::new(-208i16, -128, 32),
+        Position::new(-177i16, -113, 47),
+    );
+    assert!(vm.region_generated(a, b).await.unwrap());
+    assert!(vm.region_generated(b, a).await.unwrap());
+    let c = Position::new(-209i16, -120, 40);
+    assert!(!vm.region_generated(c, b).await.unwrap());
+    assert_eq!(vm.cached_blocks().count(), 0);
+}
+
+#[async_test]
+async fn dump_nodes_csv() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    // Block (-14, -8, 2) does not exist, so only nodes of block (-13, -8, 2) are dumped
+    let (a, b) = (
+        Position::new(-205i16, -126, 33),
+        Position::new(-212i16, -128, 32),
+    );
+    let mut csv = Vec::new();
+    let rows = mapdata.dump_nodes_csv(a, b, true, &mut csv).await.unwrap();
+    assert_eq!(rows, 4 * 3 * 2);
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("x,y,z,content,param1,param2"));
+    let node = mapdata
+        .get_mapblock(Position::new(-13i16, -8, 2))
+        .await
+        .unwrap()
+        .get_node_at(Position::ORIGIN);
+    let first_row = format!(
+        "-208,-128,32,{},{},{}",
+        node.content_str_lossy(),
+        node.param1,
+        node.param2
+    );
+    assert_eq!(lines.next(), Some(first_row.as_str()));
+    assert_eq!(lines.count() as u64, rows - 1);
+
+    let air_rows = csv.lines().filter(|line| line.contains(",air,")).count() as u64;
+    let mut without_air = Vec::new();
+    let rows_without_air = mapdata
+        .dump_nodes_csv(a, b, false, &mut without_air)
+        .await
+        .unwrap();
+    assert_eq!(rows_without_air, rows - air_rows);
+}
+
+#[async_test]
+async fn dump_wide_region_csv() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    mapdata
+        .set_mapblock(Position::new(200i16, 0, 0), &MapBlock::unloaded())
+        .await?;
+    // The region spans more than i16::MAX nodes along the x axis
+    let (a, b) = (Position::new(-30912i16, 0, 0), Position::new(30927i16, 0, 0));
+    let mut csv = Vec::new();
+    assert_eq!(mapdata.dump_nodes_csv(a, b, false, &mut csv).await?, 16);
+    let csv = String::from_utf8(csv).unwrap();
+    let xs: Vec<_> = csv
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap())
+        .collect();
+    assert_eq!(xs.first(), Some(&"3200"));
+    assert_eq!(xs.last(), Some(&"3215"));
+    Ok(())
+}
+
+#[async_test]
+async fn ping() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    mapdata.ping().await.unwrap();
+}
+
+#[async_test]
+async fn sqlite_uri() {
+    let pos = Position::new(-13i16, -8, 2);
+    let mapdata = MapData::from_sqlite_uri("file:TestWorld/map.sqlite?mode=ro&immutable=1", true)
+        .await
+        .unwrap();
+    assert!(mapdata.exists(pos).await.unwrap());
+    assert!(mapdata.set_mapblock_data(pos, &[]).await.is_err());
+    let mapdata = MapData::from_sqlite_uri("sqlite:TestWorld/map.sqlite?immutable=true", true)
+        .await
+        .unwrap();
+    assert!(mapdata.exists(pos).await.unwrap());
+    assert!(
+        MapData::from_sqlite_uri("file:TestWorld/map.sqlite?mode=nonsense", true)
+            .await
+            .is_err()
+    );
+}
+
+#[async_test]
+async fn borrowed_block_data() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let pos = Position::new(-13i16, -8, 2);
+    let owned = mapdata.get_block_data(pos).await.unwrap();
+    let len = mapdata
+        .with_block_data(pos, |data| {
+            assert_eq!(data, owned.as_slice());
+            data.len()
+        })
+        .await
+        .unwrap();
+    assert_eq!(len, owned.len());
+    assert!(matches!(
+        mapdata.with_block_data(Position::splat(2000), |_| ()).await,
+        Err(MapDataError::MapBlockNonexistent(_))
+    ));
+}
+
+#[async_test]
 async fn mapblock_miss() {
     let position = Position { x: 0, y: 0, z: 0 };
     let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
@@ -61,43 +238,117 @@ fn can_parse_mapblock() {
     MapBlock::from_data(std::fs::File::open("TestWorld/testmapblock").unwrap()).unwrap();
 }
 
-#[async_std::test]
+#[async_test]
 async fn can_parse_all_mapblocks() {
     let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
         .await
         .unwrap();
-    let positions: Vec<_> = mapdata
-        .all_mapblock_positions()
-        .await
-        .try_collect()
-        .await
-        .unwrap();
-    let blocks: Vec<_> =
-        futures::future::join_all(positions.iter().map(|pos| mapdata.get_mapblock(*pos))).await;
+    let blocks: Vec<_> = mapdata.mapblocks_buffered(8).await.collect().await;
+    assert_eq!(blocks.len(), 5923);
     let succeeded = blocks.iter().filter(|b| b.is_ok()).count();
     let failed = blocks.iter().filter(|b| b.is_err()).count();
     eprintln!("Succeeded parsed blocks: {succeeded}\nFailed blocks: {failed}");
     assert_eq!(failed, 0);
 }
 
-#[async_std::test]
+#[async_test]
+async fn blocking_work_leaves_executor_free() {
+    // The LevelDB backend and the parallel decoding rely on this
+    let slow = crate::runtime::spawn_blocking(|| {
+        std::thread::sleep(std::time::Duration::from_millis(500))
+    })
+    .fuse();
+    let timer = async_std::task::sleep(std::time::Duration::from_millis(10)).fuse();
+    futures::pin_mut!(slow, timer);
+    futures::select! {
+        () = timer => {}
+        () = slow => panic!("The blocking work finished before the timer fired"),
+    }
+}
+
+#[async_test]
+async fn parallel_decoding() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let mut serial: Vec<_> = mapdata
+        .mapblocks_buffered(4)
+        .await
+        .map_ok(|(pos, block)| (pos, block.param0))
+        .try_collect()
+        .await
+        .unwrap();
+    let mut parallel: Vec<_> = mapdata
+        .mapblocks_parallel(4, 4)
+        .await
+        .map_ok(|(pos, block)| (pos, block.param0))
+        .try_collect()
+        .await
+        .unwrap();
+    serial.sort();
+    parallel.sort();
+    assert!(serial == parallel);
+    let report = mapdata.verify_parallel(4, 4).await.unwrap();
+    assert_eq!(report.ok, 5923);
+    assert!(report.errors.is_empty());
+    // Zero is clamped to one instead of meaning unbounded
+    let report = mapdata.verify_parallel(0, 0).await.unwrap();
+    assert_eq!(report.ok, 5923);
+    assert_eq!(mapdata.verify(0).await.unwrap().ok, 5923);
+}
+
+#[async_test]
+async fn verify_world() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let report = mapdata.verify(8).await.unwrap();
+    assert_eq!(report.ok, 5923);
+    assert!(report.errors.is_empty());
+}
+
+#[async_test]
 async fn count_nodes() {
     let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
         .await
         .unwrap();
-    let count = mapdata
+    let nodes = mapdata
         .iter_mapblock_nodes(Position {
             x: -13,
             y: -8,
             z: 2,
         })
         .await
-        .unwrap()
-        .count();
-    assert_eq!(count, 4096);
+        .unwrap();
+    assert_eq!(nodes.len(), 4096);
+    assert_eq!(nodes.count(), 4096);
+}
+
+#[async_test]
+async fn iter_all_nodes() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let nodes: Vec<_> = mapdata
+        .iter_all_nodes()
+        .await
+        .take(2 * 4096)
+        .try_collect()
+        .await
+        .unwrap();
+    let first_block = nodes[0].0.mapblock_at();
+    let second_block = nodes[4096].0.mapblock_at();
+    assert_ne!(first_block, second_block);
+    for (i, (pos, _)) in nodes.iter().enumerate() {
+        let block = if i < 4096 { first_block } else { second_block };
+        assert_eq!(
+            pos.split_at_block(),
+            (block, Position::from_node_index(i as u16 % 4096))
+        );
+    }
 }
 
-#[async_std::test]
+#[async_test]
 async fn iter_node_positions() {
     let blockpos = Position {
         x: -13,
@@ -105,7 +356,7 @@ async fn iter_node_positions() {
         z: 2,
     };
 
-    let world = World::open("TestWorld");
+    let world = World::open("TestWorld").await.unwrap();
     let mapdata = world.get_map_data().await.unwrap();
     for (pos, node) in mapdata.iter_mapblock_nodes(blockpos).await.unwrap() {
         println!("{pos:?}, {node:?}");
@@ -122,7 +373,147 @@ fn node_index() {
             y: 15,
             z: 15,
         }
+    );
+    for index in 0..4096 {
+        assert_eq!(Position::from_node_index(index).as_node_index(), index);
+    }
+    assert_eq!(Position::from_node_index(256), Position::new(0i16, 0, 1));
+}
+
+#[test]
+fn split_at_block_offset_range() {
+    assert_eq!(
+        Position::new(-1i16, -1, -1).split_at_block(),
+        (Position::new(-1i16, -1, -1), Position::new(15i16, 15, 15))
+    );
+    assert_eq!(
+        Position::new(-16i16, -17, 16).split_at_block(),
+        (Position::new(-1i16, -2, 1), Position::new(0i16, 15, 0))
+    );
+    for c in i16::MIN..=i16::MAX {
+        let pos = Position::new(c, c, c);
+        let (block, offset) = pos.split_at_block();
+        assert!((0..16).contains(&offset.x), "{pos:?} -> {offset:?}");
+        assert_eq!(offset.x, offset.y);
+        assert_eq!(offset.x, offset.z);
+        assert_eq!(block.x as i32 * 16 + offset.x as i32, c as i32);
+    }
+}
+
+#[test]
+fn split_region_into_blocks() {
+    let parts: Vec<_> = Position::split_region_into_blocks(
+        Position::new(-2i16, 5, 20),
+        Position::new(17i16, 10, 20),
     )
+    .collect();
+    assert_eq!(
+        parts,
+        vec![
+            (
+                Position::new(-1i16, 0, 1),
+                Position::new(14i16, 5, 4),
+                Position::new(15i16, 10, 4)
+            ),
+            (
+                Position::new(0i16, 0, 1),
+                Position::new(0i16, 5, 4),
+                Position::new(15i16, 10, 4)
+            ),
+            (
+                Position::new(1i16, 0, 1),
+                Position::new(0i16, 5, 4),
+                Position::new(1i16, 10, 4)
+            ),
+        ]
+    );
+    // The corners may be given in any order
+    let swapped = Position::split_region_into_blocks(
+        Position::new(17i16, 10, 20),
+        Position::new(-2i16, 5, 20),
+    );
+    assert!(swapped.eq(parts));
+    let total: i32 = Position::split_region_into_blocks(Position::splat(-20), Position::splat(20))
+        .map(|(_, min, max)| {
+            let size = max - min + Position::splat(1);
+            size.x as i32 * size.y as i32 * size.z as i32
+        })
+        .sum();
+    assert_eq!(total, 41 * 41 * 41);
+}
+
+#[test]
+fn database_key_range() {
+    for v in -2048..=2047i16 {
+        for pos in [
+            Position::new(v, -2048, 2047),
+            Position::new(2047, v, -2048),
+            Position::new(-2048, 2047, v),
+        ] {
+            let key = pos.try_as_database_key().unwrap();
+            assert_eq!(Position::from_database_key(key), pos);
+        }
+    }
+    for pos in [
+        Position::new(2048i16, 0, 0),
+        Position::new(0i16, -2049, 0),
+        Position::new(0i16, 0, i16::MAX),
+    ] {
+        assert_eq!(pos.try_as_database_key(), Err(PositionRangeError(pos.into())));
+    }
+}
+
+#[test]
+fn position_overflow() {
+    let one = Position::new(1i16, 1, 1);
+    let max = Position::new(i16::MAX, i16::MAX, i16::MAX);
+    let min = Position::new(i16::MIN, i16::MIN, i16::MIN);
+    assert_eq!(max.checked_add(one), None);
+    assert_eq!(min.checked_sub(one), None);
+    assert_eq!(max.checked_mul(2), None);
+    assert_eq!(
+        Position::new(i16::MAX, 0, 0).checked_add(Position::new(0i16, 1, 0)),
+        Some(Position::new(i16::MAX, 1, 0))
+    );
+    assert_eq!(max.saturating_add(one), max);
+    assert_eq!(min.saturating_sub(one), min);
+    assert_eq!(min.saturating_mul(2), min);
+    assert_eq!(
+        Position::new(30000i16, -30000, 5).saturating_mul(2),
+        Position::new(i16::MAX, i16::MIN, 10)
+    );
+}
+
+#[test]
+fn position_from_i32() {
+    let max = i16::MAX as i32;
+    let min = i16::MIN as i32;
+    assert_eq!(
+        Position::try_from((max, min, 0)),
+        Ok(Position::new(i16::MAX, i16::MIN, 0))
+    );
+    for components in [(max + 1, 0, 0), (0, min - 1, 0), (0, 0, i32::MAX)] {
+        assert_eq!(
+            Position::try_from(components),
+            Err(PositionRangeError(components))
+        );
+    }
+    let pos = Position::new(-300i16, 12, 7);
+    assert_eq!(<(i32, i32, i32)>::from(pos), (-300, 12, 7));
+}
+
+#[test]
+fn region_sizes() {
+    use crate::positions::{block_count, node_count};
+    let (a, b) = (Position::new(-50i16, 0, 20), Position::new(49i16, 99, 119));
+    assert_eq!(node_count(a, b), 1_000_000);
+    assert_eq!(node_count(b, a), 1_000_000);
+    assert_eq!(block_count(a, b), 8 * 7 * 7);
+    assert_eq!(
+        block_count(a, b),
+        Position::split_region_into_blocks(a, b).count() as u64
+    );
+    assert_eq!(node_count(a, a), 1);
 }
 
 #[test]
@@ -145,3 +536,1620 @@ fn url_nondefault_values() {
         Ok("postgresql://u:p@localhorst:15432/mtdb".to_string())
     );
 }
+
+#[test]
+fn itemstring_splitting() {
+    type Case<'a> = (&'a [u8], Option<&'a [u8]>, &'a [u8]);
+    let cases: &[Case] = &[
+        (b"default:stone", Some(b"default"), b"stone"),
+        (b"air", None, b"air"),
+        (b"ignore", None, b"ignore"),
+        (b"default:", Some(b"default"), b""),
+        (b":default", None, b"default"),
+        (b"mod:sub:item", Some(b"mod"), b"sub:item"),
+        (b"", None, b""),
+    ];
+    for &(itemstring, modname, item) in cases {
+        assert_eq!(split_itemstring(itemstring), (modname, item));
+    }
+}
+
+fn rotate(m: [[i8; 3]; 3], v: [i8; 3]) -> [i8; 3] {
+    [0, 1, 2].map(|row| (0..3).map(|col| m[row][col] * v[col]).sum())
+}
+
+#[test]
+fn facedir_rotations() {
+    let identity = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+    assert_eq!(facedir_rotation(0), identity);
+    // Rotation around y by 90°, 180° and 270°
+    assert_eq!(facedir_rotation(1), [[0, 0, 1], [0, 1, 0], [-1, 0, 0]]);
+    assert_eq!(facedir_rotation(2), [[-1, 0, 0], [0, 1, 0], [0, 0, -1]]);
+    assert_eq!(facedir_rotation(3), [[0, 0, -1], [0, 1, 0], [1, 0, 0]]);
+    // Top pointing to z+, back pointing to y-
+    assert_eq!(facedir_rotation(4), [[1, 0, 0], [0, 0, -1], [0, 1, 0]]);
+    // Upside down
+    assert_eq!(facedir_rotation(20), [[-1, 0, 0], [0, -1, 0], [0, 0, 1]]);
+
+    // Minetest's facedir_to_dir, and the direction the top points to
+    let back = [
+        [0, 0, 1],
+        [1, 0, 0],
+        [0, 0, -1],
+        [-1, 0, 0],
+        [0, -1, 0],
+        [0, 1, 0],
+    ];
+    let back_map = [
+        0, 1, 2, 3, 4, 1, 5, 3, 5, 1, 4, 3, 0, 4, 2, 5, 0, 5, 2, 4, 0, 3, 2, 1,
+    ];
+    let top = [
+        [0, 1, 0],
+        [0, 0, 1],
+        [0, 0, -1],
+        [1, 0, 0],
+        [-1, 0, 0],
+        [0, -1, 0],
+    ];
+    for facedir in 0..24 {
+        let m = facedir_rotation(facedir);
+        let f = usize::from(facedir);
+        assert_eq!(rotate(m, [0, 0, 1]), back[back_map[f]], "facedir {facedir}");
+        assert_eq!(rotate(m, [0, 1, 0]), top[f / 4], "facedir {facedir}");
+        // Proper rotation: determinant 1
+        let det: i8 = (0..3)
+            .map(|i| {
+                m[0][i]
+                    * (m[1][(i + 1) % 3] * m[2][(i + 2) % 3]
+                        - m[1][(i + 2) % 3] * m[2][(i + 1) % 3])
+            })
+            .sum();
+        assert_eq!(det, 1, "facedir {facedir}");
+    }
+    // Color bits are ignored, invalid values are treated as 0
+    assert_eq!(facedir_rotation(0b1110_0001), facedir_rotation(1));
+    assert_eq!(facedir_rotation(24), identity);
+    assert_eq!(facedir_rotation(31), identity);
+}
+
+#[async_test]
+async fn open_nonexistent_world() {
+    match World::open("NonexistentWorld").await {
+        Err(crate::Error::InvalidWorldDirectory(path, _)) => {
+            assert_eq!(path, std::path::Path::new("NonexistentWorld"))
+        }
+        _ => panic!("Opening a missing directory should result in InvalidWorldDirectory"),
+    }
+}
+
+#[async_test]
+async fn create_sqlite_world() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let path = dir.path().join("TestWorld created");
+    let world = World::create_sqlite(&path).await?;
+    let meta = world.get_world_metadata().await?;
+    assert_eq!(meta.get("backend").unwrap(), "sqlite3");
+    assert_eq!(meta.get("world_name").unwrap(), "TestWorld created");
+    let mapdata = World::open(&path).await?.get_map_data().await?;
+    assert_eq!(mapdata.all_mapblock_positions().await.count().await, 0);
+    Ok(())
+}
+
+#[async_test]
+async fn fresh_world_without_players() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let world = World::create_sqlite(dir.path()).await?;
+    // No player has joined yet, so players.sqlite and auth.sqlite are missing
+    assert!(world.players().await?.is_empty());
+    assert!(world.auth().await?.is_empty());
+    Ok(())
+}
+
+#[async_test]
+async fn world_mt_names() -> Result<(), crate::Error> {
+    let world = World::open("TestWorld").await?;
+    assert_eq!(world.game_id().await?.as_deref(), Some("minetest"));
+    assert_eq!(world.world_name().await?.as_deref(), Some("Hallo"));
+
+    let dir = scratch_dir();
+    async_std::fs::write(dir.path().join("world.mt"), "backend = sqlite3\n").await?;
+    let world = World::new(dir.path());
+    assert_eq!(world.game_id().await?, None);
+    assert_eq!(world.world_name().await?, None);
+    Ok(())
+}
+
+#[async_test]
+async fn enabled_mods() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let world_mt = "backend = sqlite3
+load_mod_mesecons = true
+load_mod_unused = false
+gameid = minetest
+load_mod_technic = true
+";
+    async_std::fs::write(dir.path().join("world.mt"), world_mt).await?;
+    let mods = World::new(dir.path()).enabled_mods().await?;
+    assert_eq!(mods, vec!["mesecons", "technic"]);
+    Ok(())
+}
+
+#[cfg(feature = "redis")]
+#[async_test]
+#[ignore = "needs a Redis server on localhost"]
+async fn redis_positions_scan() {
+    use redis::AsyncCommands;
+
+    let hash = "minetestworld redis_positions_scan";
+    let mapdata =
+        MapData::from_redis_connection_params(url::Host::Domain("localhost".into()), None, hash)
+            .await
+            .unwrap();
+    let MapData::Redis { connection, .. } = &mapdata else {
+        unreachable!()
+    };
+    let mut connection = connection.clone();
+    let _: () = connection.del(hash).await.unwrap();
+    let data = MapBlock::unloaded().to_binary().unwrap();
+    let blocks: Vec<_> = (0..2500i16)
+        .map(|i| (Position::new(i % 50, i / 50, -i % 7), data.as_slice()))
+        .collect();
+    mapdata.set_mapblock_data_many(blocks).await.unwrap();
+
+    let mut streamed: Vec<_> = mapdata
+        .all_mapblock_positions()
+        .await
+        .try_collect()
+        .await
+        .unwrap();
+    let mut keys: Vec<_> = connection
+        .hkeys::<_, Vec<i64>>(hash)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(Position::from_database_key)
+        .collect();
+    let _: () = connection.del(hash).await.unwrap();
+    streamed.sort();
+    keys.sort();
+    assert_eq!(streamed.len(), 2500);
+    assert_eq!(streamed, keys);
+}
+
+#[cfg(not(feature = "experimental-leveldb"))]
+#[async_test]
+async fn backend_feature_disabled() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    async_std::fs::write(dir.path().join("world.mt"), "backend = leveldb\n").await?;
+    match World::new(dir.path()).get_map_data().await {
+        Err(e @ crate::Error::BackendFeatureDisabled(_)) => assert_eq!(
+            e.to_string(),
+            "The 'leveldb' backend requires the `experimental-leveldb` feature, which is disabled"
+        ),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn compiled_backends() {
+    assert_eq!(
+        supported_backends().contains(&"sqlite3"),
+        cfg!(feature = "sqlite")
+    );
+    assert_eq!(
+        supported_backends().contains(&"leveldb"),
+        cfg!(feature = "experimental-leveldb")
+    );
+}
+
+#[async_test]
+async fn strict_backend() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let path = dir.path();
+    assert_eq!(World::new(path).backend().await?, Backend::Sqlite);
+    assert!(matches!(
+        World::new(path).strict().backend().await,
+        Err(crate::Error::IOError(_))
+    ));
+    async_std::fs::write(path.join("world.mt"), "gameid = minetest\n").await?;
+    assert_eq!(World::new(path).backend().await?, Backend::Sqlite);
+    assert!(matches!(
+        World::new(path).strict().get_map_data().await,
+        Err(crate::Error::BogusBackendConfig(_))
+    ));
+    Ok(())
+}
+
+#[async_test]
+async fn map_data_reused() {
+    let world = World::open("TestWorld").await.unwrap();
+    let first = world.get_map_data().await.unwrap();
+    let second = world.get_map_data().await.unwrap();
+    match (first, second) {
+        (MapData::Sqlite(first), MapData::Sqlite(second)) => {
+            first.close().await;
+            assert!(second.is_closed());
+        }
+        _ => panic!("TestWorld is expected to use sqlite"),
+    }
+    // Another World opens its own pool
+    assert!(World::open("TestWorld")
+        .await
+        .unwrap()
+        .get_map_data()
+        .await
+        .unwrap()
+        .ping()
+        .await
+        .is_ok());
+}
+
+#[async_test]
+async fn backup_world() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let dest = dir.path();
+    let world = World::open("TestWorld").await?;
+    world.backup(dest).await?;
+    let backup = World::open(dest).await?;
+    assert_eq!(
+        backup.get_world_metadata().await?,
+        world.get_world_metadata().await?
+    );
+    let mapdata = backup.get_map_data().await?;
+    assert_eq!(mapdata.all_mapblock_positions().await.count().await, 5923);
+    // An existing backup is not overwritten
+    assert!(matches!(
+        world.backup(dest).await,
+        Err(crate::Error::IOError(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+    ));
+    // Release the backup's files before removing them
+    if let MapData::Sqlite(pool) = mapdata {
+        pool.close().await;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "redis")]
+#[async_test]
+async fn backup_unsupported_backend() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    // Nothing listens on this port, so connecting would fail with a different error
+    let world = World::create_redis(dir.path(), "localhost", Some(1), "minetest").await?;
+    let dest = scratch_dir();
+    assert!(matches!(
+        world.backup(dest.path()).await,
+        Err(crate::Error::UnsupportedBackup(backend)) if backend == "redis"
+    ));
+    Ok(())
+}
+
+#[async_test]
+async fn create_world_in_nonempty_dir() {
+    assert!(World::create_sqlite("TestWorld").await.is_err());
+}
+
+#[cfg(feature = "redis")]
+#[async_test]
+async fn create_redis_world() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let world = World::create_redis(dir.path(), "localhost", Some(6379), "minetest").await?;
+    let meta = world.get_world_metadata().await?;
+    assert_eq!(meta.get("backend").unwrap(), "redis");
+    assert_eq!(meta.get("redis_address").unwrap(), "localhost");
+    assert_eq!(meta.get("redis_port").unwrap(), "6379");
+    assert_eq!(meta.get("redis_hash").unwrap(), "minetest");
+    Ok(())
+}
+
+#[async_test]
+async fn world_metadata_roundtrip() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let world_mt = dir.path().join("world.mt");
+    async_std::fs::copy("TestWorld/world.mt", &world_mt).await?;
+    let world = World::open(dir.path()).await?;
+    let meta = world.get_world_metadata().await?;
+    world.set_world_metadata(&meta).await?;
+    assert_eq!(
+        async_std::fs::read("TestWorld/world.mt").await?,
+        async_std::fs::read(&world_mt).await?
+    );
+
+    let mut meta = meta;
+    *meta.get_mut("world_name").unwrap() = String::from("Renamed");
+    world.set_world_metadata(&meta).await?;
+    let entries: Vec<_> = world.get_world_metadata().await?.into_iter().collect();
+    assert_eq!(entries, meta.into_iter().collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn parse_crlf_settings() {
+    let meta = parse_settings(
+        "# Written on Windows\r\n  backend = sqlite3 \r\ngameid=minetest\r\n\r\n\
+         # player_backend = files\r\nnp = {\r\n\tscale = 1\r\n}\r\n",
+    );
+    assert_eq!(meta.len(), 3);
+    assert_eq!(meta["backend"], "sqlite3");
+    assert_eq!(meta["gameid"], "minetest");
+    assert_eq!(meta["np"], "{\n\tscale = 1\n}");
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+fn postgres_schema_check() {
+    let columns = |names: &[&str]| names.iter().map(|&n| String::from(n)).collect();
+    assert!(check_postgres_columns(columns(&["posx", "posy", "posz", "data", "mtime"])).is_ok());
+    match check_postgres_columns(columns(&["pos", "data"])) {
+        Err(e @ MapDataError::IncompatiblePostgresSchema(_)) => {
+            assert!(e.to_string().ends_with(r#"has ["pos", "data"]"#))
+        }
+        other => panic!("Expected IncompatiblePostgresSchema, got {other:?}"),
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_test]
+#[ignore = "needs a PostgreSQL server on localhost"]
+async fn create_postgres_world() -> Result<(), crate::Error> {
+    let dir = scratch_dir();
+    let connection_params = "host=localhost user=minetest dbname=minetestworld_test";
+    let world = World::create_postgres(dir.path(), connection_params).await?;
+    let meta = world.get_world_metadata().await?;
+    assert_eq!(meta.get("backend").unwrap(), "postgresql");
+    assert_eq!(meta.get("pgsql_connection").unwrap(), connection_params);
+    world.get_map_data().await?.ping().await?;
+    Ok(())
+}
+
+#[test]
+fn backend_names() {
+    for backend in [
+        Backend::Sqlite,
+        Backend::Postgres,
+        Backend::Redis,
+        Backend::LevelDb,
+        Backend::Unknown(String::from("dummy")),
+    ] {
+        assert_eq!(Backend::from(backend.as_str()), backend);
+    }
+}
+
+#[test]
+fn parse_player_file() {
+    let player = crate::players::parse_player_file(
+        b"name = singleplayer\nhp = 17\nposition = (-1305,85,203)\nbreath = 11\nPlayerArgsEnd\n\
+List main 1\nWidth 0\nItem default:dirt 3\nEndInventoryList\nEndInventory\n",
+    )
+    .unwrap();
+    assert_eq!(player.name, "singleplayer");
+    assert_eq!(player.hp, 17);
+    assert_eq!(player.position, (-130.5, 8.5, 20.3));
+    assert_eq!(
+        player.inventory,
+        b"List main 1\nWidth 0\nItem default:dirt 3\nEndInventoryList\nEndInventory\n"
+    );
+}
+
+#[test]
+fn unterminated_inventory() {
+    let mut mapblock = MapBlock::unloaded();
+    mapblock.node_metadata.push(NodeMetadata {
+        position: Position::new(1i16, 2, 3),
+        vars: vec![],
+        inventory: b"List main 0\nWidth 0\nEndInventoryList\n".to_vec(),
+    });
+    let binary = mapblock.to_binary().unwrap();
+    assert!(matches!(
+        MapBlock::from_data(binary.as_slice()),
+        Err(MapBlockError::BlobMalformed(_))
+    ));
+
+    let endless = b"Item default:dirt 99\n".repeat(100_000);
+    assert!(matches!(
+        crate::map_block::read_inventory(&mut endless.as_slice()),
+        Err(MapBlockError::BlobMalformed(_))
+    ));
+    // Empty lines count towards the limit as well
+    assert!(matches!(
+        crate::map_block::read_inventory(&mut std::io::repeat(b'\n')),
+        Err(MapBlockError::BlobMalformed(_))
+    ));
+}
+
+#[async_test]
+async fn read_sqlite_players() {
+    let world = World::open("TestWorld").await.unwrap();
+    let players = world.players().await.unwrap();
+    assert_eq!(players.len(), 1);
+    let player = &players[0];
+    assert_eq!(player.name, "singleplayer");
+    assert_eq!(player.hp, 20);
+    assert_eq!(player.position, (-130.5, 8.5, 20.3));
+    let inventory = String::from_utf8(player.inventory.clone()).unwrap();
+    assert!(inventory.starts_with(
+        "List main 4\nWidth 0\nItem default:pick_stone 1 1200\nEmpty\nItem default:dirt 42\nEmpty\nEndInventoryList\nList craft 9\nWidth 3\n"
+    ));
+    assert!(inventory.ends_with("EndInventoryList\nEndInventory\n"));
+}
+
+#[async_test]
+async fn read_sqlite_auth() {
+    let world = World::open("TestWorld").await.unwrap();
+    let auth = world.auth().await.unwrap();
+    assert_eq!(auth.len(), 2);
+    assert_eq!(auth[0].name, "admin");
+    assert_eq!(auth[0].password_hash, "#1#c2FsdA#aGFzaA");
+    assert_eq!(auth[0].privileges, vec!["interact", "privs", "server"]);
+    assert_eq!(auth[1].name, "singleplayer");
+    assert_eq!(auth[1].last_login, 1700000000);
+    assert_eq!(auth[1].privileges, vec!["fly", "interact", "shout"]);
+}
+
+#[async_test]
+async fn read_map_meta() {
+    let map_meta = World::open("TestWorld")
+        .await
+        .unwrap()
+        .map_meta()
+        .await
+        .unwrap();
+    assert_eq!(map_meta.seed, 2942764402361394437);
+    assert_eq!(map_meta.water_level, 1);
+    assert_eq!(map_meta.params.get("mg_name").unwrap(), "v7");
+    assert!(map_meta
+        .params
+        .get("mg_biome_np_heat")
+        .unwrap()
+        .ends_with("persistence = 0.5\n}"));
+    // The seed in the noise group must not shadow the map seed
+    assert_eq!(map_meta.params.get("seed").unwrap(), "2942764402361394437");
+}
+
+#[test]
+fn mapblock_compression_levels() {
+    let block =
+        MapBlock::from_data(std::fs::File::open("TestWorld/testmapblock").unwrap()).unwrap();
+    for level in [1, 19] {
+        let data = block.to_binary_with_level(level).unwrap();
+        let decoded = MapBlock::from_data(data.as_slice()).unwrap();
+        assert_eq!(decoded.param0, block.param0);
+        assert_eq!(decoded.param1, block.param1);
+        assert_eq!(decoded.param2, block.param2);
+        assert_eq!(decoded.name_id_mappings, block.name_id_mappings);
+        assert_eq!(decoded.timestamp, block.timestamp);
+    }
+}
+
+#[test]
+fn mapblock_from_bytes() {
+    let data = std::fs::read("TestWorld/testmapblock").unwrap();
+    let from_reader = MapBlock::from_data(data.as_slice()).unwrap();
+    let from_bytes = MapBlock::from_bytes(&data).unwrap();
+    assert_eq!(from_bytes.flags, from_reader.flags);
+    assert_eq!(from_bytes.timestamp, from_reader.timestamp);
+    assert_eq!(from_bytes.param0, from_reader.param0);
+    assert_eq!(from_bytes.param1, from_reader.param1);
+    assert_eq!(from_bytes.param2, from_reader.param2);
+    assert_eq!(from_bytes.name_id_mappings, from_reader.name_id_mappings);
+    assert_eq!(
+        from_bytes.node_metadata.len(),
+        from_reader.node_metadata.len()
+    );
+    assert!(matches!(
+        MapBlock::from_bytes(&[]),
+        Err(MapBlockError::ReadError(_))
+    ));
+    assert!(matches!(
+        MapBlock::from_bytes(&[28]),
+        Err(MapBlockError::MapVersionError(28))
+    ));
+}
+
+#[test]
+fn mapblock_zstd_dictionary() {
+    let data = std::fs::read("TestWorld/testmapblock").unwrap();
+    let mapblock = MapBlock::from_data(data.as_slice()).unwrap();
+    let dict = b"default:stone default:dirt default:dirt_with_grass air ignore".repeat(16);
+    let binary = mapblock.to_binary_with_dict(&dict).unwrap();
+    let decoded = MapBlock::from_data_with_dict(binary.as_slice(), &dict).unwrap();
+    assert_eq!(decoded.param0, mapblock.param0);
+    assert_eq!(decoded.name_id_mappings, mapblock.name_id_mappings);
+    // Without the dictionary, the data cannot be decoded
+    assert!(MapBlock::from_data(binary.as_slice()).is_err());
+}
+
+#[test]
+fn mapblock_trailing_bytes() {
+    let data = std::fs::read("TestWorld/testmapblock").unwrap();
+    let mut content = zstd::decode_all(&data[1..]).unwrap();
+    content.extend_from_slice(b"garbage");
+    let mut data = vec![29];
+    data.extend(zstd::encode_all(content.as_slice(), 0).unwrap());
+    match MapBlock::from_data(data.as_slice()) {
+        Err(MapBlockError::BlobMalformed(msg)) => assert!(msg.starts_with("7 ")),
+        _ => panic!("Trailing bytes should result in MapBlockError::BlobMalformed"),
+    }
+}
+
+#[test]
+fn set_content_out_of_block() {
+    let mut block = MapBlock::unloaded();
+    for pos in [
+        Position::new(16i16, 0, 0),
+        Position::new(0i16, -1, 0),
+        Position::new(0i16, 0, 16),
+    ] {
+        assert_eq!(block.try_set_content(pos, 1), Err(OutOfBlockError(pos)));
+        assert_eq!(block.try_set_param1(pos, 1), Err(OutOfBlockError(pos)));
+        assert_eq!(block.try_set_param2(pos, 1), Err(OutOfBlockError(pos)));
+    }
+    assert!(block.param0.iter().all(|&id| id == 0));
+
+    block
+        .try_set_content(Position::new(15i16, 15, 15), 1)
+        .unwrap();
+    assert_eq!(block.param0[4095], 1);
+}
+
+#[test]
+fn oversized_name_id_mappings() {
+    let mut block = MapBlock::unloaded();
+    block.name_id_mappings = (0..=u16::MAX)
+        .map(|id| (id, format!("mod:node{id}").into_bytes()))
+        .collect();
+    assert_eq!(block.name_id_mappings.len(), 65536);
+    let error = block.to_binary().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn uniform_mapblocks() {
+    let mut block = MapBlock::unloaded();
+    let node = block.is_uniform().unwrap();
+    assert_eq!(node.param0, b"ignore");
+    assert_eq!((node.param1, node.param2), (0, 0));
+
+    block.set_param2(Position::new(3i16, 4, 5), 1);
+    assert!(block.is_uniform().is_none());
+    block.set_param2(Position::new(3i16, 4, 5), 0);
+    let stone = block.get_or_create_content_id(b"default:stone");
+    block.set_content(Position::new(15i16, 15, 15), stone);
+    assert!(block.is_uniform().is_none());
+}
+
+#[test]
+fn fill_mapblock() {
+    let mut block =
+        MapBlock::from_data(std::fs::File::open("TestWorld/testmapblock").unwrap()).unwrap();
+    block.fill(&Node {
+        param0: b"default:stone".to_vec(),
+        param1: 15,
+        param2: 3,
+    });
+    let node = block.get_node_at(Position::new(7i16, 2, 11));
+    assert_eq!(node.param0, b"default:stone");
+    assert_eq!((node.param1, node.param2), (15, 3));
+    assert_eq!(
+        block.content_names().collect::<Vec<_>>(),
+        vec![b"default:stone"]
+    );
+    assert!(block.is_uniform().is_some());
+    assert!(block.node_metadata.is_empty());
+    assert!(block.node_timers.is_empty());
+    assert!(block.static_objects.is_empty());
+}
+
+#[test]
+fn new_filled_mapblock() {
+    let air = Node {
+        param0: b"air".to_vec(),
+        param1: 0x0f,
+        param2: 0,
+    };
+    let mapblock = MapBlock::new_filled(&air);
+    assert_eq!(mapblock.name_id_mappings.len(), 1);
+    assert_eq!(mapblock.timestamp, 0);
+    for index in 0..4096 {
+        assert_eq!(mapblock.get_node_at(Position::from_node_index(index)), air);
+    }
+    let decoded = MapBlock::from_data(mapblock.to_binary().unwrap().as_slice()).unwrap();
+    assert_eq!(decoded.get_node_at(Position::ORIGIN), air);
+}
+
+#[test]
+fn serialized_len_estimate() {
+    fn uncompressed_len(mapblock: &MapBlock) -> usize {
+        let binary = mapblock.to_binary().unwrap();
+        1 + zstd::decode_all(&binary[1..]).unwrap().len()
+    }
+
+    let mut mapblock = MapBlock::unloaded();
+    assert_eq!(
+        mapblock.estimate_serialized_len(),
+        uncompressed_len(&mapblock)
+    );
+
+    mapblock.get_or_create_content_id(b"default:chest");
+    mapblock.node_metadata.push(NodeMetadata {
+        position: Position::new(1i16, 2, 3),
+        vars: vec![NodeVar {
+            key: b"infotext".to_vec(),
+            value: b"Chest".to_vec(),
+            is_private: false,
+        }],
+        inventory: b"List main 0\nWidth 0\nEndInventoryList\nEndInventory\n".to_vec(),
+    });
+    mapblock.static_objects.push(StaticObject {
+        type_id: 7,
+        x: 1000,
+        y: -2000,
+        z: 3000,
+        data: b"__builtin:item".to_vec(),
+    });
+    mapblock.node_timers.push(NodeTimer {
+        position: Position::new(4i16, 5, 6),
+        timeout: 1000,
+        elapsed: 10,
+    });
+    assert_eq!(
+        mapblock.estimate_serialized_len(),
+        uncompressed_len(&mapblock)
+    );
+}
+
+#[test]
+fn metadata_and_objects_roundtrip() {
+    let mut mapblock = MapBlock::unloaded();
+    mapblock.node_metadata.push(NodeMetadata {
+        position: Position::new(1i16, 2, 3),
+        vars: vec![
+            NodeVar {
+                key: b"infotext".to_vec(),
+                value: b"Chest".to_vec(),
+                is_private: false,
+            },
+            NodeVar {
+                key: b"owner".to_vec(),
+                value: b"singleplayer".to_vec(),
+                is_private: true,
+            },
+        ],
+        inventory: b"List main 0\nWidth 0\nEndInventoryList\nEndInventory\n".to_vec(),
+    });
+    mapblock.static_objects.push(StaticObject {
+        type_id: 7,
+        x: 1000,
+        y: -2000,
+        z: 3000,
+        data: b"__builtin:item".to_vec(),
+    });
+    mapblock.node_timers.push(NodeTimer {
+        position: Position::new(4i16, 5, 6),
+        timeout: 1000,
+        elapsed: 10,
+    });
+
+    let decoded = MapBlock::from_data(mapblock.to_binary().unwrap().as_slice()).unwrap();
+    assert_eq!(decoded.node_metadata.len(), 1);
+    let metadatum = &decoded.node_metadata[0];
+    assert_eq!(metadatum.position, Position::new(1i16, 2, 3));
+    assert_eq!(metadatum.vars.len(), 2);
+    assert_eq!(metadatum.vars[0].key, b"infotext");
+    assert_eq!(metadatum.vars[0].value, b"Chest");
+    assert!(!metadatum.vars[0].is_private);
+    assert_eq!(metadatum.vars[1].key, b"owner");
+    assert_eq!(metadatum.vars[1].value, b"singleplayer");
+    assert!(metadatum.vars[1].is_private);
+    assert_eq!(metadatum.inventory, mapblock.node_metadata[0].inventory);
+
+    assert_eq!(decoded.static_objects.len(), 1);
+    let object = &decoded.static_objects[0];
+    assert_eq!(object.type_id, 7);
+    assert_eq!((object.x, object.y, object.z), (1000, -2000, 3000));
+    assert_eq!(object.data, b"__builtin:item");
+
+    assert_eq!(decoded.node_timers.len(), 1);
+    assert_eq!(decoded.node_timers[0].position, Position::new(4i16, 5, 6));
+    assert_eq!(decoded.node_timers[0].timeout, 1000);
+    assert_eq!(decoded.node_timers[0].elapsed, 10);
+}
+
+#[test]
+fn huge_length_field() {
+    let binary = MapBlock::unloaded().to_binary().unwrap();
+    let mut raw = zstd::decode_all(&binary[1..]).unwrap();
+    // Replace the empty node metadata, static objects, and node timers
+    raw.truncate(raw.len() - 7);
+    raw.extend_from_slice(&[
+        2, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1, b'k', 0xff, 0xff, 0xff, 0xf0,
+    ]);
+    raw.extend_from_slice(b"truncated");
+    let mut corrupt = vec![29];
+    corrupt.extend(zstd::encode_all(raw.as_slice(), 0).unwrap());
+    match MapBlock::from_data(corrupt.as_slice()) {
+        Err(MapBlockError::BlobMalformed(msg)) => assert!(msg.contains("metadata value")),
+        other => panic!("Expected BlobMalformed, got {other:?}"),
+    }
+}
+
+#[test]
+fn rename_content() {
+    let stone = |content: &[u8]| Node {
+        param0: content.to_vec(),
+        param1: 0,
+        param2: 0,
+    };
+    let mut mapblock = MapBlock::new_filled(&stone(b"oldmod:stone"));
+    assert!(!mapblock.rename_content(b"oldmod:dirt", b"newmod:dirt"));
+    assert!(mapblock.rename_content(b"oldmod:stone", b"newmod:stone"));
+    assert_eq!(mapblock.name_id_mappings.len(), 1);
+    assert_eq!(
+        mapblock.get_node_at(Position::ORIGIN).param0,
+        b"newmod:stone"
+    );
+
+    // Merge into a content that is already present
+    let air = mapblock.get_or_create_content_id(b"air");
+    mapblock.set_content(Position::ORIGIN, air);
+    assert!(mapblock.rename_content(b"newmod:stone", b"air"));
+    assert_eq!(mapblock.name_id_mappings.len(), 1);
+    assert!((0..4096).all(|i| mapblock.get_node_at(Position::from_node_index(i)).is_air()));
+}
+
+#[test]
+fn node_iter_len() {
+    let mut iter = crate::map_block::NodeIter::from(MapBlock::unloaded(), Position::ORIGIN);
+    assert_eq!(iter.len(), 4096);
+    iter.next();
+    assert_eq!(iter.len(), 4095);
+    assert_eq!(iter.by_ref().count(), 4095);
+    assert_eq!(iter.len(), 0);
+}
+
+#[test]
+fn used_content_ids() {
+    let mut block = MapBlock::unloaded();
+    assert_eq!(block.content_ids_used(), std::collections::BTreeSet::from([0]));
+    let stone = block.get_or_create_content_id(b"default:stone");
+    block.set_content(Position::new(1i16, 2, 3), stone);
+    assert_eq!(
+        block.content_ids_used(),
+        std::collections::BTreeSet::from([0, stone])
+    );
+    block.set_content(Position::new(1i16, 2, 3), 0);
+    assert_eq!(block.content_ids_used(), std::collections::BTreeSet::from([0]));
+}
+
+#[test]
+fn dangling_content_id() {
+    let mut mapblock = MapBlock::unloaded();
+    mapblock.set_content(Position::new(1i16, 0, 0), 7);
+    assert_eq!(mapblock.content_from_id_opt(7), None);
+    assert_eq!(
+        mapblock.content_from_id(7),
+        crate::map_block::CONTENT_UNKNOWN
+    );
+    let iter = crate::map_block::NodeIter::from(mapblock, Position::new(1i16, 0, 0));
+    let dangling: Vec<_> = iter
+        .checked()
+        .filter_map(|(pos, node)| node.err().map(|id| (pos, id)))
+        .collect();
+    assert_eq!(dangling, vec![(Position::new(17i16, 0, 0), 7)]);
+}
+
+#[test]
+fn node_timer_durations() {
+    use std::time::Duration;
+    let mut timer = NodeTimer {
+        position: Position::ORIGIN,
+        timeout: 1500,
+        elapsed: -3,
+    };
+    assert_eq!(timer.timeout_duration(), Duration::from_millis(1500));
+    assert_eq!(timer.elapsed_duration(), Duration::ZERO);
+    timer.set_elapsed_duration(Duration::from_micros(2500));
+    assert_eq!(timer.elapsed, 2);
+    timer.set_timeout_duration(Duration::from_secs(u64::MAX));
+    assert_eq!(timer.timeout, i32::MAX);
+    assert_eq!(
+        timer.timeout_duration(),
+        Duration::from_millis(i32::MAX as u64)
+    );
+}
+
+#[test]
+fn node_predicates() {
+    let mapblock = MapBlock::unloaded();
+    for index in 0..4096 {
+        let node = mapblock.get_node_at(Position::from_node_index(index));
+        assert!(node.is_ignore() && !node.is_air() && !node.is_unknown());
+    }
+    let air = MapBlock::new_filled(&Node {
+        param0: b"air".to_vec(),
+        param1: 0,
+        param2: 0,
+    });
+    assert!(air.get_node_at(Position::ORIGIN).is_air());
+    let mut unknown = MapBlock::unloaded();
+    unknown.name_id_mappings.clear();
+    assert!(unknown.get_node_at(Position::ORIGIN).is_unknown());
+}
+
+#[test]
+fn mapblock_builder_roundtrip() {
+    let torch = Node {
+        param0: b"default:torch".to_vec(),
+        param1: 0xee,
+        param2: 1,
+    };
+    let pos = Position::new(3i16, 4, 5);
+    let mapblock = crate::map_block::MapBlockBuilder::new()
+        .fill(&Node {
+            param0: b"default:stone".to_vec(),
+            param1: 0,
+            param2: 0,
+        })
+        .timestamp(1234)
+        .lighting_complete(0x0fff)
+        .underground(true)
+        .day_night_differs(true)
+        .node(pos, &torch)
+        .build();
+    let decoded = MapBlock::from_data(mapblock.to_binary().unwrap().as_slice()).unwrap();
+    assert_eq!(decoded.timestamp, 1234);
+    assert_eq!(decoded.lighting_complete, 0x0fff);
+    assert_eq!(decoded.flags, 0x03);
+    assert_eq!(decoded.get_node_at(pos), torch);
+    assert_eq!(
+        decoded.get_node_at(Position::ORIGIN).param0,
+        b"default:stone"
+    );
+    assert_eq!(decoded.summary().distinct_contents, 2);
+}
+
+#[test]
+#[should_panic]
+fn mapblock_builder_outside() {
+    let node = MapBlock::unloaded().get_node_at(Position::ORIGIN);
+    crate::map_block::MapBlockBuilder::new().node(Position::splat(16), &node);
+}
+
+#[test]
+fn interned_nodes() {
+    let mut interner = crate::map_block::ContentInterner::new();
+    let mut mapblock = MapBlock::unloaded();
+    let air = mapblock.get_or_create_content_id(b"air");
+    mapblock.set_content(Position::ORIGIN, air);
+    let other = MapBlock::unloaded();
+
+    let nodes: Vec<_> = mapblock.iter_nodes_interned(&mut interner).collect();
+    let other_nodes: Vec<_> = other.iter_nodes_interned(&mut interner).collect();
+    assert_eq!(nodes.len(), 4096);
+    assert_eq!(&*nodes[0].1.param0, b"air");
+    assert_eq!(nodes[1].0, Position::new(1i16, 0, 0));
+    assert_eq!(nodes[1].1.to_node(), mapblock.get_node_at(nodes[1].0));
+    assert!(std::sync::Arc::ptr_eq(
+        &nodes[1].1.param0,
+        &nodes[2].1.param0
+    ));
+    assert!(std::sync::Arc::ptr_eq(
+        &nodes[1].1.param0,
+        &other_nodes[0].1.param0
+    ));
+    // air, ignore, and unknown
+    assert_eq!(interner.len(), 3);
+}
+
+#[test]
+fn mapblock_iter_region() {
+    let mut mapblock = MapBlock::unloaded();
+    let air = mapblock.get_or_create_content_id(b"air");
+    mapblock.set_content(Position::splat(15), air);
+    let corner: Vec<_> = mapblock
+        .iter_region(Position::splat(14), Position::splat(20))
+        .collect();
+    assert_eq!(corner.len(), 8);
+    assert_eq!(corner[0].0, Position::splat(14));
+    assert_eq!(corner[1].0, Position::new(15i16, 14, 14));
+    assert_eq!(
+        corner[7],
+        (
+            Position::splat(15),
+            mapblock.get_node_ref_at(Position::splat(15))
+        )
+    );
+    assert!(corner[7].1.param0 == b"air");
+    assert!(corner[..7].iter().all(|(_, node)| node.param0 == b"ignore"));
+
+    let all = mapblock.iter_region(Position::splat(-5), Position::splat(100));
+    assert_eq!(all.count(), 4096);
+    let outside = mapblock.iter_region(Position::splat(16), Position::splat(20));
+    assert_eq!(outside.count(), 0);
+    let reversed = mapblock.iter_region(Position::splat(3), Position::splat(2));
+    assert_eq!(reversed.count(), 0);
+}
+
+#[test]
+fn write_unsupported_widths() {
+    let mut mapblock = MapBlock::unloaded();
+    mapblock.content_width = 1;
+    let err = mapblock.to_binary().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    mapblock.content_width = crate::map_block::CONTENT_WIDTH;
+    mapblock.params_width = 3;
+    assert!(mapblock.to_binary_with_dict(&[]).is_err());
+    mapblock.params_width = crate::map_block::PARAMS_WIDTH;
+    assert!(mapblock.to_binary().is_ok());
+}
+
+#[test]
+fn content_id_headroom() {
+    let mut mapblock = MapBlock::unloaded();
+    assert_eq!(mapblock.content_id_count(), 1);
+    assert_eq!(mapblock.content_id_headroom(), 65534);
+    for id in 1..u16::MAX {
+        let content = format!("mod:node{id}").into_bytes();
+        mapblock.name_id_mappings.insert(id, content);
+    }
+    assert_eq!(mapblock.content_id_count(), 65535);
+    assert_eq!(mapblock.content_id_headroom(), 0);
+    // Still within the limits of the map format
+    assert!(mapblock.to_binary().is_ok());
+}
+
+#[test]
+fn mapblock_summary() {
+    let summary = MapBlock::unloaded().summary();
+    assert_eq!(summary.distinct_contents, 1);
+    assert_eq!(summary.content_counts, vec![(b"ignore".to_vec(), 4096)]);
+    assert_eq!(
+        (summary.metadata, summary.timers, summary.objects),
+        (0, 0, 0)
+    );
+    assert!(!summary.underground);
+    assert_eq!(
+        summary.to_string(),
+        "1 contents (ignore: 4096), 0 metadata, 0 timers, 0 objects, timestamp 4294967295"
+    );
+
+    let mut mapblock = MapBlock::unloaded();
+    let air = mapblock.get_or_create_content_id(b"air");
+    mapblock.set_content(Position::ORIGIN, air);
+    mapblock.set_underground(true);
+    let summary = mapblock.summary();
+    assert_eq!(
+        summary.content_counts,
+        vec![(b"ignore".to_vec(), 4095), (b"air".to_vec(), 1)]
+    );
+    assert!(summary.to_string().ends_with(", underground"));
+}
+
+#[test]
+fn mapblock_flags() {
+    let mut mapblock = MapBlock::unloaded();
+    assert!(!mapblock.is_underground() && !mapblock.day_night_differs());
+    mapblock.set_underground(true);
+    mapblock.set_day_night_differs(true);
+    assert_eq!(mapblock.flags, 0x03);
+    assert!(mapblock.is_underground() && mapblock.day_night_differs());
+    mapblock.set_underground(false);
+    assert_eq!(mapblock.flags, 0x02);
+    assert!(!mapblock.is_underground() && mapblock.day_night_differs());
+}
+
+#[async_test]
+async fn mapblock_exists() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    assert!(mapdata.exists(Position::new(-13i16, -8, 2)).await.unwrap());
+    assert!(!mapdata.exists(Position::new(0i16, 0, 0)).await.unwrap());
+}
+
+#[async_test]
+async fn read_mapblock_header() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let data = mapdata
+        .get_block_data(Position::new(-13i16, -8, 2))
+        .await
+        .unwrap();
+    let header = MapBlock::read_header(data.as_slice()).unwrap();
+    let block = MapBlock::from_data(data.as_slice()).unwrap();
+    assert_eq!(header.map_format_version, block.map_format_version);
+    assert_eq!(header.flags, block.flags);
+    assert_eq!(header.lighting_complete, block.lighting_complete);
+    assert_eq!(header.timestamp, block.timestamp);
+}
+
+#[async_test]
+async fn block_timestamp() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let pos = Position::new(-13i16, -8, 2);
+    assert_eq!(
+        mapdata.get_block_timestamp(pos).await.unwrap(),
+        mapdata.get_mapblock(pos).await.unwrap().timestamp
+    );
+    assert!(matches!(
+        mapdata
+            .get_block_timestamp(Position::new(2000i16, 0, 0))
+            .await,
+        Err(MapDataError::MapBlockNonexistent(_))
+    ));
+}
+
+#[async_test]
+async fn concurrent_sqlite_handles() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let file = dir.path().join("map.sqlite");
+    async_std::fs::copy("TestWorld/map.sqlite", &file).await?;
+    let opts = SqliteOptions {
+        wal: true,
+        ..Default::default()
+    };
+    let writer = MapData::from_sqlite_file_with_opts(&file, false, opts.clone()).await?;
+    let reader = MapData::from_sqlite_file_with_opts(&file, false, opts).await?;
+    let pos = Position::new(-13i16, -8, 2);
+    let data = reader.get_block_data(pos).await?;
+    let new_pos = Position::new(0i16, 0, 0);
+    let (written, read) = futures::join!(
+        writer.set_mapblock_data(new_pos, &data),
+        reader.get_block_data(pos)
+    );
+    written?;
+    assert_eq!(read?, data);
+    assert_eq!(reader.get_block_data(new_pos).await?, data);
+    Ok(())
+}
+
+#[async_test]
+async fn sqlite_single_connection() {
+    let opts = SqliteOptions {
+        max_connections: 1,
+        ..Default::default()
+    };
+    let mapdata = MapData::from_sqlite_file_with_opts("TestWorld/map.sqlite", true, opts)
+        .await
+        .unwrap();
+    if let MapData::Sqlite(pool) = &mapdata {
+        assert_eq!(pool.options().get_max_connections(), 1);
+    }
+    assert!(mapdata.exists(Position::new(-13i16, -8, 2)).await.unwrap());
+}
+
+#[async_test]
+async fn optimize_sqlite() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let file = dir.path().join("map.sqlite");
+    async_std::fs::copy("TestWorld/map.sqlite", &file).await?;
+    let size_before = async_std::fs::metadata(&file).await?.len();
+    let mapdata = MapData::from_sqlite_file(&file, false).await?;
+    let data = mapdata.get_block_data(Position::new(-13i16, -8, 2)).await?;
+    mapdata
+        .set_mapblock_data(Position::new(0i16, 0, 0), &data)
+        .await?;
+    if let MapData::Sqlite(pool) = &mapdata {
+        sqlx::query("DELETE FROM blocks WHERE pos % 2 = 0")
+            .execute(pool)
+            .await?;
+    }
+    mapdata.optimize().await?;
+    let size_after = async_std::fs::metadata(&file).await?.len();
+    assert!(size_after < size_before);
+    Ok(())
+}
+
+#[async_test]
+async fn sorted_positions_without_duplicates() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    let (a, b) = (Position::new(1i16, 0, 0), Position::new(-1i16, 5, 0));
+    if let MapData::Sqlite(pool) = &mapdata {
+        // A table without primary key, as a malformed database could have
+        sqlx::query("DROP TABLE blocks").execute(pool).await?;
+        sqlx::query("CREATE TABLE blocks (pos INT NOT NULL, data BLOB)")
+            .execute(pool)
+            .await?;
+        for pos in [a, b, a] {
+            sqlx::query("INSERT INTO blocks VALUES (?, x'')")
+                .bind(pos.as_database_key())
+                .execute(pool)
+                .await?;
+        }
+    }
+    assert_eq!(mapdata.all_mapblock_positions().await.count().await, 3);
+    let sorted: Vec<_> = mapdata
+        .all_mapblock_positions_sorted()
+        .await
+        .try_collect()
+        .await?;
+    assert_eq!(sorted, vec![b, a]);
+    Ok(())
+}
+
+#[async_test]
+async fn cancelled_commit() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let path = dir.path().join("map.sqlite");
+    let mapdata = MapData::from_sqlite_file(&path, false).await?;
+
+    // A failing write in the middle of a batch leaves the database untouched
+    let data = MapBlock::unloaded().to_binary()?;
+    let batch = [
+        (Position::ORIGIN, &data[..]),
+        (Position::splat(3000), &data[..]),
+    ];
+    assert!(mapdata.set_mapblock_data_many(batch).await.is_err());
+    assert!(!mapdata.exists(Position::ORIGIN).await?);
+
+    let mut vm = VoxelManip::new(mapdata);
+    for x in 0..4 {
+        vm.set_content(Position::new(x * 16i16, 0, 0), b"default:stone")
+            .await?;
+    }
+    assert!(vm.commit().now_or_never().is_none());
+    assert_eq!(vm.tainted_blocks().count(), 4);
+
+    vm.commit().await?;
+    assert_eq!(vm.tainted_blocks().count(), 0);
+    let mapdata = MapData::from_sqlite_file(&path, true).await?;
+    assert_eq!(mapdata.all_mapblock_positions().await.count().await, 4);
+    let mapblock = mapdata.get_mapblock(Position::new(3i16, 0, 0)).await?;
+    assert_eq!(
+        mapblock.get_node_at(Position::ORIGIN).param0,
+        b"default:stone"
+    );
+    Ok(())
+}
+
+#[async_test]
+async fn voxelmanip_resolves_aliases() {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let aliases = std::collections::HashMap::from([(b"stone".to_vec(), b"default:stone".to_vec())]);
+    let mut vm = VoxelManip::new(mapdata).with_registry(aliases);
+    let (a, b) = (Position::new(1i16, 2, 3), Position::new(1i16, 2, 4));
+    vm.set_content(a, b"stone").await.unwrap();
+    vm.set_content(b, b"default:dirt").await.unwrap();
+    assert_eq!(vm.get_node(a).await.unwrap().param0, b"default:stone");
+    assert_eq!(vm.get_node(b).await.unwrap().param0, b"default:dirt");
+}
+
+#[async_test]
+async fn lighting_complete_flags() {
+    let mut block = MapBlock::unloaded();
+    block.set_lighting_complete_all(false);
+    assert_eq!(block.lighting_complete, 0);
+    block.set_lighting_complete_all(true);
+    assert_eq!(block.lighting_complete, 0xffff);
+
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    let mut vm = VoxelManip::new(mapdata);
+    vm.set_lighting_complete(
+        Position::new(14i16, 0, 0),
+        Position::new(17i16, 0, 0),
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(vm.tainted_blocks().count(), 2);
+    assert!(vm.tainted_blocks().all(|(_, b)| b.lighting_complete == 0));
+}
+
+#[async_test]
+async fn voxelmanip_set_nodes() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let path = dir.path().join("map.sqlite");
+    let mut vm = VoxelManip::new(MapData::from_sqlite_file(&path, false).await?);
+    let node = |content: &[u8], param2| Node {
+        param0: content.to_vec(),
+        param1: 0,
+        param2,
+    };
+    vm.set_nodes([
+        (Position::new(14i16, 0, 0), node(b"default:stone", 0)),
+        (Position::new(15i16, 0, 0), node(b"default:stone", 1)),
+        (Position::new(16i16, 0, 0), node(b"default:dirt", 2)),
+        (Position::new(17i16, 0, 0), node(b"default:stone", 3)),
+        (Position::new(14i16, 0, 0), node(b"default:glass", 4)),
+    ])
+    .await?;
+    assert_eq!(vm.tainted_blocks().count(), 2);
+    vm.commit().await?;
+    let committed = vm.map_data().all_mapblock_positions().await.count().await;
+    assert_eq!(committed, 2);
+
+    let mapdata = MapData::from_sqlite_file(&path, true).await?;
+    let expected = [
+        (14i16, node(b"default:glass", 4)),
+        (15, node(b"default:stone", 1)),
+        (16, node(b"default:dirt", 2)),
+        (17, node(b"default:stone", 3)),
+        (18, node(b"ignore", 0)),
+    ];
+    for (x, node) in expected {
+        assert_eq!(mapdata.get_node(Position::new(x, 0, 0)).await?, node);
+    }
+    Ok(())
+}
+
+#[async_test]
+async fn set_mapblock_checked() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    let pos = Position::new(-1i16, -2048, 2047);
+    let mut mapblock = MapBlock::unloaded();
+    mapblock.timestamp = 42;
+    mapdata.set_mapblock_checked(pos, &mapblock).await?;
+    let positions: Vec<_> = mapdata.all_mapblock_positions().await.try_collect().await?;
+    assert_eq!(positions, vec![pos]);
+    assert_eq!(mapdata.get_mapblock(pos).await?.timestamp, 42);
+    assert!(matches!(
+        mapdata
+            .set_mapblock_checked(Position::splat(2048), &mapblock)
+            .await,
+        Err(MapDataError::PositionOutOfRange(_))
+    ));
+    Ok(())
+}
+
+#[async_test]
+async fn world_bounds() -> Result<(), MapDataError> {
+    let mapdata = MapData::from_sqlite_file("TestWorld/map.sqlite", true)
+        .await
+        .unwrap();
+    assert_eq!(
+        mapdata.bounds().await.unwrap(),
+        Some((Position::new(-13i16, -13, 2), Position::new(13i16, 13, 13)))
+    );
+
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    assert_eq!(mapdata.bounds().await?, None);
+    mapdata
+        .set_mapblock(Position::ORIGIN, &MapBlock::unloaded())
+        .await?;
+    assert_eq!(
+        mapdata.bounds().await?,
+        Some((Position::ORIGIN, Position::ORIGIN))
+    );
+    Ok(())
+}
+
+#[async_test]
+async fn copy_between_backends() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let source = MapData::from_sqlite_file("TestWorld/map.sqlite", true).await?;
+    let dest = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    assert_eq!(source.copy_to(&dest).await?, 5923);
+    assert_eq!(dest.all_mapblock_positions().await.count().await, 5923);
+    let pos = Position::new(-13i16, -8, 2);
+    assert_eq!(
+        source.get_block_data(pos).await?,
+        dest.get_block_data(pos).await?
+    );
+    Ok(())
+}
+
+#[async_test]
+async fn clear_mapdata() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    let block = MapBlock::unloaded();
+    for x in 0..3i16 {
+        mapdata.set_mapblock(Position::new(x, 0, 0), &block).await?;
+    }
+    assert_eq!(mapdata.clear().await?, 3);
+    assert_eq!(mapdata.all_mapblock_positions().await.count().await, 0);
+    assert_eq!(mapdata.clear().await?, 0);
+    Ok(())
+}
+
+#[cfg(feature = "experimental-leveldb")]
+#[async_test]
+async fn leveldb_roundtrip() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let path = dir.path().join("map.db");
+    leveldb_rs::DB::create(&path).map_err(MapDataError::LevelDbError)?;
+    let source = MapData::from_sqlite_file("TestWorld/map.sqlite", true).await?;
+    let pos = Position::new(-13i16, -8, 2);
+    let data = source.get_block_data(pos).await?;
+
+    let leveldb = MapData::from_leveldb(&path)?;
+    assert!(!leveldb.exists(pos).await?);
+    leveldb.set_mapblock_data(pos, &data).await?;
+    assert_eq!(leveldb.get_block_data(pos).await?, data);
+    let positions: Vec<_> = leveldb.all_mapblock_positions().await.try_collect().await?;
+    assert_eq!(positions, vec![pos]);
+    leveldb.delete_mapblock(pos).await?;
+    assert!(!leveldb.exists(pos).await?);
+    Ok(())
+}
+
+#[async_test]
+async fn delete_sqlite_mapblock() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    let pos = Position::new(1i16, 2, 3);
+    mapdata.set_mapblock(pos, &MapBlock::unloaded()).await?;
+    assert!(mapdata.exists(pos).await?);
+    mapdata.delete_mapblock(pos).await?;
+    assert!(!mapdata.exists(pos).await?);
+    // Deleting a missing block is fine
+    mapdata.delete_mapblock(pos).await?;
+    let out_of_range = Position::new(2048i16, 0, 0);
+    assert!(matches!(
+        mapdata
+            .set_mapblock(out_of_range, &MapBlock::unloaded())
+            .await,
+        Err(MapDataError::PositionOutOfRange(_))
+    ));
+    Ok(())
+}
+
+#[async_test]
+async fn set_many_mapblocks() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    let data = MapBlock::unloaded().to_binary()?;
+    let positions = [Position::new(1i16, 2, 3), Position::new(-4i16, 5, -6)];
+    mapdata
+        .set_mapblock_data_many(positions.iter().map(|&pos| (pos, data.as_slice())))
+        .await?;
+    for pos in positions {
+        assert_eq!(mapdata.get_block_data(pos).await?, data);
+    }
+    Ok(())
+}
+
+#[async_test]
+async fn read_node_region() {
+    let world = World::open("TestWorld").await.unwrap();
+    let mut vm = world.get_voxel_manip(false).await.unwrap();
+    let min = Position::new(-13i16, -8, 2) * 16;
+    let max = min + Position::new(15i16, 15, 15);
+    let mut expected = vec![];
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let pos = Position::new(x, y, z);
+                expected.push((pos, vm.get_node(pos).await.unwrap()));
+            }
+        }
+    }
+
+    let region = vm.get_nodes_region(max, min).await.unwrap();
+    assert_eq!(region.dimensions(), (16, 16, 16));
+    assert_eq!((region.min(), region.max()), (min, max));
+    for (pos, node) in expected {
+        let node_ref = region.get(pos).unwrap();
+        assert_eq!(node_ref.param0, node.param0.as_slice());
+        assert_eq!(
+            (node_ref.param1, node_ref.param2),
+            (node.param1, node.param2)
+        );
+    }
+    assert!(region.get(max + Position::new(1i16, 0, 0)).is_none());
+}
+
+#[async_test]
+async fn voxel_manip_transaction() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let file = dir.path().join("map.sqlite");
+    let mut vm = VoxelManip::new(MapData::from_sqlite_file(&file, false).await?);
+    let (committed, discarded) = (Position::new(1i16, 2, 3), Position::new(100i16, 2, 3));
+    vm.transaction(|vm| {
+        Box::pin(async move { vm.set_content(committed, b"default:stone").await })
+    })
+    .await?;
+    // Changes made before a transaction survive its failure
+    let pending = Position::new(200i16, 2, 3);
+    vm.set_content(pending, b"default:dirt").await?;
+    let failed: Result<(), MapDataError> = vm
+        .transaction(|vm| {
+            Box::pin(async move {
+                vm.set_content(discarded, b"default:stone").await?;
+                Err(MapDataError::MapBlockNonexistent(discarded))
+            })
+        })
+        .await;
+    assert!(failed.is_err());
+    assert_eq!(vm.get_node(discarded).await?.param0, b"ignore");
+    assert_eq!(vm.get_node(pending).await?.param0, b"default:dirt");
+
+    let mapdata = MapData::from_sqlite_file(&file, true).await?;
+    assert!(mapdata.exists(committed.mapblock_at()).await?);
+    assert!(mapdata.exists(pending.mapblock_at()).await?);
+    assert!(!mapdata.exists(discarded.mapblock_at()).await?);
+    Ok(())
+}
+
+#[async_test]
+async fn voxel_manip_commit_block() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let file = dir.path().join("map.sqlite");
+    let mut vm = VoxelManip::new(MapData::from_sqlite_file(&file, false).await?);
+    vm.set_content(Position::ORIGIN, b"default:stone").await?;
+    vm.set_content(Position::X * 16, b"default:stone").await?;
+    assert!(vm.commit_block(Position::ORIGIN).await?);
+    assert!(!vm.commit_block(Position::ORIGIN).await?);
+    let tainted: Vec<_> = vm.tainted_blocks().map(|(pos, _)| pos).collect();
+    assert_eq!(tainted, vec![Position::X]);
+
+    let mapdata = MapData::from_sqlite_file(&file, true).await?;
+    assert!(mapdata.exists(Position::ORIGIN).await?);
+    assert!(!mapdata.exists(Position::X).await?);
+    vm.discard_changes();
+    Ok(())
+}
+
+#[async_test]
+async fn voxel_manip_set_node_borrowed() {
+    let world = World::open("TestWorld").await.unwrap();
+    let mut vm = world.get_voxel_manip(false).await.unwrap();
+    for x in 0..32i16 {
+        vm.set_node_borrowed(Position::new(x, 0, 0), b"air", 0x0f, 3)
+            .await
+            .unwrap();
+    }
+    let node = vm.get_node(Position::new(20i16, 0, 0)).await.unwrap();
+    assert_eq!(
+        node,
+        Node {
+            param0: b"air".to_vec(),
+            param1: 0x0f,
+            param2: 3
+        }
+    );
+    vm.discard_changes();
+}
+
+#[async_test]
+async fn recalc_light() {
+    let world = World::open("TestWorld").await.unwrap();
+    let mut vm = world.get_voxel_manip(false).await.unwrap();
+    let is_opaque = |content: &[u8]| content == b"default:stone";
+    let light_source = |content: &[u8]| if content == b"default:torch" { 13 } else { 0 };
+    async fn light_at(vm: &mut VoxelManip, offset: Position) -> u8 {
+        let pos = Position::new(0i16, 30000, 0) + offset;
+        vm.get_node(pos).await.unwrap().param1
+    }
+
+    // An open column is lit by the sun everywhere
+    let base = Position::new(0i16, 30000, 0);
+    vm.recalc_light(
+        base,
+        base + Position::new(2i16, 5, 2),
+        is_opaque,
+        light_source,
+    )
+    .await
+    .unwrap();
+    assert_eq!(light_at(&mut vm, Position::new(1i16, 0, 1)).await, 0x0f);
+
+    // A stone box with a torch inside
+    for x in 0..5i16 {
+        for y in 0..5i16 {
+            for z in 0..5i16 {
+                if [x, y, z].iter().any(|&c| c == 0 || c == 4) {
+                    let pos = base + Position::new(x, y, z);
+                    vm.set_content(pos, b"default:stone").await.unwrap();
+                }
+            }
+        }
+    }
+    vm.set_content(base + Position::new(1i16, 1, 1), b"default:torch")
+        .await
+        .unwrap();
+    vm.recalc_light(
+        base,
+        base + Position::new(4i16, 4, 4),
+        is_opaque,
+        light_source,
+    )
+    .await
+    .unwrap();
+    assert_eq!(light_at(&mut vm, Position::new(1i16, 1, 1)).await, 0xdd);
+    assert_eq!(light_at(&mut vm, Position::new(2i16, 1, 1)).await, 0xcc);
+    assert_eq!(light_at(&mut vm, Position::new(3i16, 3, 3)).await, 0x77);
+    // No sunlight gets through the roof, and walls stay dark
+    assert_eq!(light_at(&mut vm, Position::new(0i16, 1, 1)).await, 0);
+    vm.discard_changes();
+}
+
+#[async_test]
+async fn voxel_manip_replace_content() {
+    let world = World::open("TestWorld").await.unwrap();
+    let mut vm = world.get_voxel_manip(false).await.unwrap();
+    let base = Position::new(0i16, 30000, 0);
+    for offset in [
+        Position::X * 14,
+        Position::X * 15,
+        Position::X * 17,
+        Position::X * 30,
+    ] {
+        vm.set_content(base + offset, b"default:stone")
+            .await
+            .unwrap();
+    }
+    let region = (base + Position::X * 15, base + Position::X * 20);
+    let replaced = vm
+        .replace_content(b"default:stone", b"default:wood", Some(region))
+        .await
+        .unwrap();
+    assert_eq!(replaced, 2);
+    assert_eq!(
+        vm.get_node(base + Position::X * 17).await.unwrap().param0,
+        b"default:wood"
+    );
+    assert_eq!(
+        vm.get_node(base + Position::X * 14).await.unwrap().param0,
+        b"default:stone"
+    );
+
+    let replaced = vm
+        .replace_content(b"default:stone", b"default:wood", None)
+        .await
+        .unwrap();
+    assert_eq!(replaced, 2);
+    assert_eq!(
+        vm.get_node(base + Position::X * 30).await.unwrap().param0,
+        b"default:wood"
+    );
+    vm.discard_changes();
+}
+
+#[async_test]
+async fn voxel_manip_tainted_blocks() {
+    let world = World::open("TestWorld").await.unwrap();
+    let mut vm = world.get_voxel_manip(false).await.unwrap();
+    vm.visit(Position::new(-208i16, -128, 32)).await.unwrap();
+    vm.set_content(Position::new(0i16, 0, 0), b"default:stone")
+        .await
+        .unwrap();
+    vm.set_content(Position::new(1i16, 1, 1), b"default:stone")
+        .await
+        .unwrap();
+    vm.set_content(Position::new(16i16, 0, 0), b"default:stone")
+        .await
+        .unwrap();
+    let mut tainted: Vec<_> = vm.tainted_blocks().map(|(pos, _)| pos).collect();
+    tainted.sort_by_key(|pos| pos.x);
+    assert_eq!(
+        tainted,
+        vec![Position::new(0i16, 0, 0), Position::new(1i16, 0, 0)]
+    );
+    assert_eq!(vm.cached_blocks().count(), 3);
+    vm.discard_changes();
+}
+
+#[async_test]
+async fn corrupt_mapblock_position() -> Result<(), MapDataError> {
+    let dir = scratch_dir();
+    let mapdata = MapData::from_sqlite_file(dir.path().join("map.sqlite"), false).await?;
+    let pos = Position::new(4i16, -5, 6);
+    mapdata.set_mapblock_data(pos, b"\x1d garbage").await?;
+    match mapdata.get_mapblock(pos).await {
+        Err(MapDataError::InvalidMapBlock(error_pos, _)) => assert_eq!(error_pos, pos),
+        other => panic!("Expected InvalidMapBlock, got {other:?}"),
+    }
+    mapdata.set_mapblock_data(pos, b"\x19").await?;
+    match mapdata.get_mapblock(pos).await {
+        Err(MapDataError::InvalidMapBlock(error_pos, MapBlockError::MapVersionError(25))) => {
+            assert_eq!(error_pos, pos)
+        }
+        other => panic!("Expected a version error, got {other:?}"),
+    }
+    let report = mapdata.verify(2).await?;
+    assert_eq!(report.ok, 0);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].0, pos);
+    Ok(())
+}
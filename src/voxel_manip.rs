@@ -1,28 +1,139 @@
 //! Contains a type to more high-level world reading and writing
 
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
+use crate::map_block::NodeRef;
 use crate::{MapBlock, MapData, MapDataError, Node, Position};
+use futures::future::BoxFuture;
 type Result<T> = std::result::Result<T, MapDataError>;
 
+/// Light level of direct sunlight
+const LIGHT_SUN: u8 = 15;
+/// Highest light level a light source can emit
+const LIGHT_MAX: u8 = 14;
+
+/// Spreads light from every lit node to its non-opaque neighbours, losing one level per step
+///
+/// `light` and `opaque` are dense buffers of the given dimensions, with x varying fastest.
+fn spread_light(light: &mut [u8], opaque: &[bool], (dx, dy, dz): (usize, usize, usize)) {
+    let mut queue: VecDeque<usize> = (0..light.len()).filter(|&i| light[i] > 1).collect();
+    while let Some(i) = queue.pop_front() {
+        let (x, y, z) = (i % dx, i / dx % dy, i / (dx * dy));
+        let neighbours = [
+            (x > 0).then(|| i - 1),
+            (x + 1 < dx).then(|| i + 1),
+            (y > 0).then(|| i - dx),
+            (y + 1 < dy).then(|| i + dx),
+            (z > 0).then(|| i - dx * dy),
+            (z + 1 < dz).then(|| i + dx * dy),
+        ];
+        for n in neighbours.into_iter().flatten() {
+            if !opaque[n] && light[n] + 1 < light[i] {
+                light[n] = light[i] - 1;
+                queue.push_back(n);
+            }
+        }
+    }
+}
+
+/// Resolves content aliases, as registered by mods with `minetest.register_alias`
+///
+/// Resolving aliases from the mods themselves would require a Lua runtime,
+/// so the registry has to be supplied by the caller; see [`VoxelManip::with_registry`].
+///
+/// ```
+/// use minetestworld::voxel_manip::ContentRegistry;
+/// use std::collections::HashMap;
+///
+/// let aliases = HashMap::from([(b"stone".to_vec(), b"default:stone".to_vec())]);
+/// assert_eq!(aliases.resolve_alias(b"stone"), Some(b"default:stone".to_vec()));
+/// assert_eq!(aliases.resolve_alias(b"default:dirt"), None);
+/// ```
+pub trait ContentRegistry: Send + Sync {
+    /// Returns the itemstring `alias` stands for, or `None` if it is no alias
+    fn resolve_alias(&self, alias: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl ContentRegistry for HashMap<Vec<u8>, Vec<u8>> {
+    fn resolve_alias(&self, alias: &[u8]) -> Option<Vec<u8>> {
+        self.get(alias).cloned()
+    }
+}
+
 struct CacheEntry {
     mapblock: MapBlock,
     tainted: bool,
 }
 
+/// A dense copy of the nodes in a cuboid region
+///
+/// The content type strings are borrowed from the [`VoxelManip`] cache.
+/// See [`VoxelManip::get_nodes_region`].
+pub struct RegionBuffer<'a> {
+    min: Position,
+    max: Position,
+    nodes: Vec<NodeRef<'a>>,
+}
+
+impl<'a> RegionBuffer<'a> {
+    /// The corner of the region with the smallest coordinates
+    pub fn min(&self) -> Position {
+        self.min
+    }
+
+    /// The corner of the region with the largest coordinates
+    pub fn max(&self) -> Position {
+        self.max
+    }
+
+    /// The number of nodes along the x, y, and z axes
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        let extent = |min: i16, max: i16| (max as i32 - min as i32 + 1) as usize;
+        (
+            extent(self.min.x, self.max.x),
+            extent(self.min.y, self.max.y),
+            extent(self.min.z, self.max.z),
+        )
+    }
+
+    /// Returns the node at the given world position, if it lies within the region
+    pub fn get(&self, node_pos: Position) -> Option<NodeRef<'a>> {
+        let inside = |v: i16, min: i16, max: i16| (min..=max).contains(&v);
+        if !(inside(node_pos.x, self.min.x, self.max.x)
+            && inside(node_pos.y, self.min.y, self.max.y)
+            && inside(node_pos.z, self.min.z, self.max.z))
+        {
+            return None;
+        }
+        let (dx, dy, _) = self.dimensions();
+        let offset = |v: i16, min: i16| (v as i32 - min as i32) as usize;
+        let index = offset(node_pos.x, self.min.x)
+            + dx * (offset(node_pos.y, self.min.y) + dy * offset(node_pos.z, self.min.z));
+        Some(self.nodes[index])
+    }
+
+    /// All nodes of the region, with x varying fastest, then y, then z
+    pub fn nodes(&self) -> &[NodeRef<'a>] {
+        &self.nodes
+    }
+}
+
 /// In-memory world data cache that allows easy handling of single nodes.
 ///
 /// It is an abstraction on top of the MapBlocks the world data consists of.
 /// It allows fast reading from and writing to the world.
 ///
-/// All changes to the world have to be committed via [`VoxelManip::commit`].
+/// All changes to the world have to be committed via [`VoxelManip::commit`]
+/// or made inside a [`VoxelManip::transaction`].
 /// Before this, they are only present in VoxelManip's local cache and lost after drop.
+/// Dropping a VoxelManip with uncommitted changes logs a warning.
 ///
-/// ⚠️ You want to do a world backup before modifying the map data.
+/// ⚠️ You want to do a world backup before modifying the map data, e.g. with [`World::backup`](crate::World::backup).
 pub struct VoxelManip {
     map: MapData,
     mapblock_cache: HashMap<Position, CacheEntry>,
+    registry: Option<Box<dyn ContentRegistry>>,
 }
 
 impl VoxelManip {
@@ -31,9 +142,32 @@ impl VoxelManip {
         VoxelManip {
             map,
             mapblock_cache: HashMap::new(),
+            registry: None,
         }
     }
 
+    /// Lets the setters of this VoxelManip resolve content aliases with `registry`
+    ///
+    /// Contents that are no alias are set as given.
+    pub fn with_registry(mut self, registry: impl ContentRegistry + 'static) -> Self {
+        self.registry = Some(Box::new(registry));
+        self
+    }
+
+    /// Returns the map data backend this VoxelManip reads from and writes to
+    ///
+    /// This allows to run queries on the same connection pool.
+    /// Changes made through it bypass the cache, so [commit](`VoxelManip::commit`) first
+    /// and beware that cached blocks are not updated.
+    pub fn map_data(&self) -> &MapData {
+        &self.map
+    }
+
+    /// Looks up `content` in the registry, if there is one
+    fn resolve_alias(&self, content: &[u8]) -> Option<Vec<u8>> {
+        self.registry.as_ref()?.resolve_alias(content)
+    }
+
     /// Return a cache entry containing the given mapblock
     async fn get_entry(&mut self, mapblock_pos: Position) -> Result<&mut CacheEntry> {
         match self.mapblock_cache.entry(mapblock_pos) {
@@ -67,6 +201,36 @@ impl VoxelManip {
         Ok(self.get_mapblock(blockpos).await?.get_node_at(nodepos))
     }
 
+    /// Reads all nodes in the cuboid spanned by the two corners `a` and `b`
+    ///
+    /// Both corners are part of the region.
+    /// All map blocks covering the region are loaded into the cache first.
+    pub async fn get_nodes_region(&mut self, a: Position, b: Position) -> Result<RegionBuffer<'_>> {
+        let min = Position::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Position::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let (min_block, max_block) = (min.mapblock_at(), max.mapblock_at());
+        for z in min_block.z..=max_block.z {
+            for y in min_block.y..=max_block.y {
+                for x in min_block.x..=max_block.x {
+                    self.get_entry(Position::new(x, y, z)).await?;
+                }
+            }
+        }
+
+        let mut nodes = Vec::new();
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let (blockpos, nodepos) = Position::new(x, y, z).split_at_block();
+                    // All blocks of the region have been loaded above
+                    let mapblock = &self.mapblock_cache[&blockpos].mapblock;
+                    nodes.push(mapblock.get_node_ref_at(nodepos));
+                }
+            }
+        }
+        Ok(RegionBuffer { min, max, nodes })
+    }
+
     /// Do something with the mapblock at `blockpos` and mark it as modified
     async fn modify_mapblock(
         &mut self,
@@ -84,21 +248,83 @@ impl VoxelManip {
     /// ⚠️ The change will be present locally only. To modify the map,
     /// the change has to be written back via [`VoxelManip::commit`].
     pub async fn set_node(&mut self, node_pos: Position, node: Node) -> Result<()> {
+        self.set_node_borrowed(node_pos, &node.param0, node.param1, node.param2)
+            .await
+    }
+
+    /// Like [`VoxelManip::set_node`], but without requiring an owned [`Node`]
+    ///
+    /// The content name is passed as a byte slice, so the caller does not have to build
+    /// a [`Node`] for every call.
+    ///
+    /// ⚠️ Until the change is [commited](`VoxelManip::commit`),
+    /// the node will only be changed in the cache.
+    pub async fn set_node_borrowed(
+        &mut self,
+        node_pos: Position,
+        content: &[u8],
+        param1: u8,
+        param2: u8,
+    ) -> Result<()> {
+        let resolved = self.resolve_alias(content);
+        let content = resolved.as_deref().unwrap_or(content);
         let (blockpos, nodepos) = node_pos.split_at_block();
         self.modify_mapblock(blockpos, |mapblock| {
-            let content_id = mapblock.get_or_create_content_id(&node.param0);
+            let content_id = mapblock.get_or_create_content_id(content);
             mapblock.set_content(nodepos, content_id);
-            mapblock.set_param1(nodepos, node.param1);
-            mapblock.set_param2(nodepos, node.param2);
+            mapblock.set_param1(nodepos, param1);
+            mapblock.set_param2(nodepos, param2);
         })
         .await
     }
 
+    /// Sets many nodes at once
+    ///
+    /// The nodes are grouped by their map block, so that every map block
+    /// is loaded only once and all of its nodes are set together.
+    /// If a position occurs more than once, the last node wins.
+    ///
+    /// ⚠️ Until the change is [commited](`VoxelManip::commit`),
+    /// the nodes will only be changed in the cache.
+    pub async fn set_nodes(
+        &mut self,
+        nodes: impl IntoIterator<Item = (Position, Node)>,
+    ) -> Result<()> {
+        let mut blocks: HashMap<Position, Vec<(Position, Node)>> = HashMap::new();
+        for (node_pos, mut node) in nodes {
+            if let Some(resolved) = self.resolve_alias(&node.param0) {
+                node.param0 = resolved;
+            }
+            let (blockpos, nodepos) = node_pos.split_at_block();
+            blocks.entry(blockpos).or_default().push((nodepos, node));
+        }
+        for (blockpos, nodes) in blocks {
+            self.modify_mapblock(blockpos, |mapblock| {
+                let mut last_content: Option<(Vec<u8>, u16)> = None;
+                for (nodepos, node) in nodes {
+                    let content_id = match &last_content {
+                        Some((content, id)) if *content == node.param0 => *id,
+                        _ => {
+                            let id = mapblock.get_or_create_content_id(&node.param0);
+                            last_content = Some((node.param0, id));
+                            id
+                        }
+                    };
+                    mapblock.set_content(nodepos, content_id);
+                    mapblock.set_param1(nodepos, node.param1);
+                    mapblock.set_param2(nodepos, node.param2);
+                }
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
     /// Sets the content string at this world position
     ///
     /// `content` has to be the unique [itemstring](https://wiki.minetest.net/Itemstrings).
-    /// The use of aliases is not possible, because it would require a Lua runtime
-    /// loading all mods.
+    /// Aliases are only resolved if a [`ContentRegistry`] was supplied with
+    /// [`VoxelManip::with_registry`], as the mods' aliases would require a Lua runtime.
     ///
     /// ```ignore
     /// vm.set_content(Position::new(8,9,10), b"default:stone").await?;
@@ -107,6 +333,8 @@ impl VoxelManip {
     /// ⚠️ Until the change is [commited](`VoxelManip::commit`),
     /// the node will only be changed in the cache.
     pub async fn set_content(&mut self, node_pos: Position, content: &[u8]) -> Result<()> {
+        let resolved = self.resolve_alias(content);
+        let content = resolved.as_deref().unwrap_or(content);
         let (blockpos, nodepos) = node_pos.split_at_block();
         self.modify_mapblock(blockpos, |mapblock| {
             let content_id = mapblock.get_or_create_content_id(content);
@@ -139,12 +367,172 @@ impl VoxelManip {
         .await
     }
 
+    /// Recomputes the light values of all nodes in the cuboid spanned by the two corners `a` and `b`
+    ///
+    /// Since this crate has no node definitions, the caller decides which contents
+    /// block light (`is_opaque`) and how much light a content emits (`light_source`, capped at 14).
+    ///
+    /// Sunlight enters the region from its top face and travels down until it hits an opaque node.
+    /// From there, and from all light sources, light spreads to non-opaque neighbours,
+    /// losing one level per node. Light from outside the region is not considered.
+    /// The results are stored in [`Node::param1`]: the lower four bits contain the day light,
+    /// the upper four bits the night light, which only comes from light sources.
+    ///
+    /// ⚠️ Until the change is [commited](`VoxelManip::commit`),
+    /// the nodes will only be changed in the cache.
+    pub async fn recalc_light(
+        &mut self,
+        a: Position,
+        b: Position,
+        is_opaque: impl Fn(&[u8]) -> bool,
+        light_source: impl Fn(&[u8]) -> u8,
+    ) -> Result<()> {
+        let region = self.get_nodes_region(a, b).await?;
+        let min = region.min();
+        let dims @ (dx, dy, dz) = region.dimensions();
+        let opaque: Vec<bool> = region.nodes().iter().map(|n| is_opaque(n.param0)).collect();
+        let mut night: Vec<u8> = region
+            .nodes()
+            .iter()
+            .map(|n| light_source(n.param0).min(LIGHT_MAX))
+            .collect();
+        let mut day = night.clone();
+
+        for z in 0..dz {
+            for x in 0..dx {
+                for y in (0..dy).rev() {
+                    let i = x + dx * (y + dy * z);
+                    if opaque[i] {
+                        break;
+                    }
+                    day[i] = LIGHT_SUN;
+                }
+            }
+        }
+        spread_light(&mut day, &opaque, dims);
+        spread_light(&mut night, &opaque, dims);
+
+        for (i, (day, night)) in day.into_iter().zip(night).enumerate() {
+            let offset = Position::new(
+                (i % dx) as i16,
+                (i / dx % dy) as i16,
+                (i / (dx * dy)) as i16,
+            );
+            self.set_param1(min + offset, day | night << 4).await?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the content `from` with `to` in the cuboid spanned by the corners of `region`
+    ///
+    /// Without a region, all cached map blocks are processed instead.
+    /// Returns the number of replaced nodes.
+    ///
+    /// ⚠️ Until the change is [commited](`VoxelManip::commit`),
+    /// the nodes will only be changed in the cache.
+    pub async fn replace_content(
+        &mut self,
+        from: &[u8],
+        to: &[u8],
+        region: Option<(Position, Position)>,
+    ) -> Result<u64> {
+        if from == to {
+            return Ok(0);
+        }
+        let Some((a, b)) = region else {
+            let mut count = 0;
+            for entry in self.mapblock_cache.values_mut() {
+                let Some(from_id) = entry.mapblock.get_content_id(from) else {
+                    continue;
+                };
+                let replaced = entry.mapblock.param0.iter().filter(|&&id| id == from_id);
+                let replaced = replaced.count() as u64;
+                if replaced > 0 {
+                    entry.mapblock.rename_content(from, to);
+                    entry.tainted = true;
+                    count += replaced;
+                }
+            }
+            return Ok(count);
+        };
+
+        let mut count = 0;
+        for (blockpos, min, max) in Position::split_region_into_blocks(a, b) {
+            let entry = self.get_entry(blockpos).await?;
+            let Some(from_id) = entry.mapblock.get_content_id(from) else {
+                continue;
+            };
+            let mut to_id = None;
+            for z in min.z..=max.z {
+                for y in min.y..=max.y {
+                    for x in min.x..=max.x {
+                        let index = Position::new(x, y, z).as_node_index() as usize;
+                        if entry.mapblock.param0[index] == from_id {
+                            let to_id = *to_id
+                                .get_or_insert_with(|| entry.mapblock.get_or_create_content_id(to));
+                            entry.mapblock.param0[index] = to_id;
+                            entry.tainted = true;
+                            count += 1;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Marks the lighting of all map blocks overlapping the region as complete or incomplete
+    ///
+    /// The region spans from `a` to `b`, both included, in world node positions.
+    /// See [`MapBlock::set_lighting_complete_all`].
+    ///
+    /// ⚠️ Until the change is [commited](`VoxelManip::commit`),
+    /// the blocks will only be changed in the cache.
+    pub async fn set_lighting_complete(
+        &mut self,
+        a: Position,
+        b: Position,
+        complete: bool,
+    ) -> Result<()> {
+        for (blockpos, _, _) in Position::split_region_into_blocks(a, b) {
+            self.modify_mapblock(blockpos, |mapblock| {
+                mapblock.set_lighting_complete_all(complete)
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
     /// Returns true if this world position is cached
     pub fn is_in_cache(&self, node_pos: Position) -> bool {
         let blockpos = node_pos.mapblock_at();
         self.mapblock_cache.contains_key(&blockpos)
     }
 
+    /// Iterates over all cached mapblocks together with their mapblock positions
+    pub fn cached_blocks(&self) -> impl Iterator<Item = (Position, &MapBlock)> {
+        self.mapblock_cache
+            .iter()
+            .map(|(&pos, entry)| (pos, &entry.mapblock))
+    }
+
+    /// Iterates over all mapblocks with uncommitted changes together with their mapblock positions
+    pub fn tainted_blocks(&self) -> impl Iterator<Item = (Position, &MapBlock)> {
+        self.mapblock_cache
+            .iter()
+            .filter(|(_, entry)| entry.tainted)
+            .map(|(&pos, entry)| (pos, &entry.mapblock))
+    }
+
+    /// Checks whether all map blocks covering the cuboid spanned by `a` and `b` are generated
+    ///
+    /// This asks the backend via [`MapData::region_generated`] and leaves the cache untouched.
+    /// Blocks that exist only in the cache, because they were modified but not yet committed,
+    /// do not count as generated.
+    pub async fn region_generated(&self, a: Position, b: Position) -> Result<bool> {
+        self.map.region_generated(a, b).await
+    }
+
     /// Ensures that this world position is in the cache
     pub async fn visit(&mut self, node_pos: Position) -> Result<()> {
         let blockpos = node_pos.mapblock_at();
@@ -157,15 +545,88 @@ impl VoxelManip {
     /// Without this, all changes made with [`VoxelManip::set_node`], [`VoxelManip::set_content`],
     /// [`VoxelManip::set_param1`], and [`VoxelManip::set_param2`] are lost when this
     /// instance is dropped.
+    ///
+    /// All modified mapblocks are written with [`MapData::set_mapblock_data_many`],
+    /// which is a single transaction for SQLite and Postgres.
+    /// The mapblocks are only marked as committed after all of them have been written.
+    /// So if the commit fails or its future is dropped (e.g. on a timeout),
+    /// the changes stay in the cache and a later commit writes them again.
     pub async fn commit(&mut self) -> Result<()> {
-        // Write modified mapblocks back into the map data
-        for (&pos, cache_entry) in self.mapblock_cache.iter_mut() {
+        let mut blocks = Vec::new();
+        for (&pos, cache_entry) in self.mapblock_cache.iter() {
             if cache_entry.tainted {
-                self.map.set_mapblock(pos, &cache_entry.mapblock).await?;
-                cache_entry.tainted = false;
+                blocks.push((pos, cache_entry.mapblock.to_binary()?));
             }
         }
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        self.map
+            .set_mapblock_data_many(blocks.iter().map(|(pos, data)| (*pos, data.as_slice())))
+            .await?;
 
+        for cache_entry in self.mapblock_cache.values_mut() {
+            cache_entry.tainted = false;
+        }
         Ok(())
     }
+
+    /// Writes the mapblock at `blockpos` back into the map data, if it was modified
+    ///
+    /// Other modified mapblocks stay in the cache uncommitted.
+    /// Returns whether the mapblock had uncommitted changes.
+    pub async fn commit_block(&mut self, blockpos: Position) -> Result<bool> {
+        match self.mapblock_cache.get_mut(&blockpos) {
+            Some(cache_entry) if cache_entry.tainted => {
+                self.map
+                    .set_mapblock(blockpos, &cache_entry.mapblock)
+                    .await?;
+                cache_entry.tainted = false;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Runs `f` on this VoxelManip and commits its changes afterwards
+    ///
+    /// Changes made before are committed first, so the transaction starts without any.
+    /// If that commit fails, `f` is not run and the earlier changes stay in the cache.
+    /// If `f` or the final commit fails, the changes made by `f` are discarded from the cache.
+    /// Because closures cannot return borrowing futures yet, the future has to be boxed:
+    ///
+    /// ```ignore
+    /// vm.transaction(|vm| Box::pin(async move {
+    ///     vm.set_content(Position::new(8, 9, 10), b"default:stone").await
+    /// })).await?;
+    /// ```
+    pub async fn transaction<T, E, F>(&mut self, f: F) -> std::result::Result<T, E>
+    where
+        F: for<'a> FnOnce(&'a mut VoxelManip) -> BoxFuture<'a, std::result::Result<T, E>>,
+        E: From<MapDataError>,
+    {
+        self.commit().await.map_err(E::from)?;
+        let result = match f(self).await {
+            Ok(value) => self.commit().await.map(|_| value).map_err(E::from),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.discard_changes();
+        }
+        result
+    }
+
+    /// Removes all modified mapblocks from the cache, undoing uncommitted changes
+    pub fn discard_changes(&mut self) {
+        self.mapblock_cache.retain(|_, entry| !entry.tainted);
+    }
+}
+
+impl Drop for VoxelManip {
+    fn drop(&mut self) {
+        let uncommitted = self.mapblock_cache.values().filter(|e| e.tainted).count();
+        if uncommitted > 0 {
+            log::warn!("VoxelManip dropped with {uncommitted} uncommitted mapblock(s)");
+        }
+    }
 }
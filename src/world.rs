@@ -1,117 +1,596 @@
 //! Contains the [`World`] along with [`WorldError`]
 
+#[cfg(feature = "sqlite")]
+use crate::auth::{self, AuthRecord};
+use crate::players::{self, Player};
+use crate::runtime as fs;
 use crate::MapData;
 use crate::MapDataError;
 use crate::VoxelManip;
-use async_std::fs;
-use async_std::fs::File;
-use async_std::io::BufReader;
-use async_std::prelude::*;
+use futures::lock::Mutex;
+use indexmap::IndexMap;
+#[cfg(feature = "postgres")]
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 #[cfg(feature = "url")]
 use url::Url;
 
+/// The settings of a world's `world.mt`, in the order they appear in the file
+pub type WorldMetadata = IndexMap<String, String>;
+
+/// The mapgen parameters of a world, as stored in `map_meta.txt`
+#[derive(Debug, Clone)]
+pub struct MapMeta {
+    /// The seed of the map generator
+    pub seed: u64,
+    /// The name of the map generator, e.g. `v7` or `flat`
+    pub mg_name: String,
+    /// The height of the water surface
+    pub water_level: i16,
+    /// All parameters, including the ones above, in the order they appear in the file
+    ///
+    /// Groups like noise parameters are kept verbatim as a multi-line value.
+    pub params: WorldMetadata,
+}
+
+/// A map data backend, as named by the `backend` setting in `world.mt`
+///
+/// ```
+/// use minetestworld::world::Backend;
+///
+/// assert_eq!(Backend::from("sqlite3"), Backend::Sqlite);
+/// assert_eq!(Backend::Postgres.as_str(), "postgresql");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// The SQLite backend, stored in `map.sqlite` (`sqlite3`)
+    Sqlite,
+    /// The PostgreSQL backend (`postgresql`)
+    Postgres,
+    /// The Redis backend (`redis`)
+    Redis,
+    /// The LevelDB backend, stored in `map.db` (`leveldb`)
+    LevelDb,
+    /// A backend this crate does not know about
+    Unknown(String),
+}
+
+impl Backend {
+    /// Returns the name that identifies this backend in `world.mt`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Backend::Sqlite => "sqlite3",
+            Backend::Postgres => "postgresql",
+            Backend::Redis => "redis",
+            Backend::LevelDb => "leveldb",
+            Backend::Unknown(name) => name,
+        }
+    }
+}
+
+impl From<&str> for Backend {
+    fn from(name: &str) -> Self {
+        match name {
+            "sqlite3" => Backend::Sqlite,
+            "postgresql" => Backend::Postgres,
+            "redis" => Backend::Redis,
+            "leveldb" => Backend::LevelDb,
+            _ => Backend::Unknown(String::from(name)),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returns the names of the map data backends this crate was compiled with
+///
+/// The names are the ones used for the `backend` setting in `world.mt`.
+///
+/// ```
+/// use minetestworld::world::supported_backends;
+///
+/// for backend in supported_backends() {
+///     println!("{backend}");
+/// }
+/// ```
+pub fn supported_backends() -> &'static [&'static str] {
+    &[
+        #[cfg(feature = "sqlite")]
+        "sqlite3",
+        #[cfg(feature = "postgres")]
+        "postgresql",
+        #[cfg(feature = "redis")]
+        "redis",
+        #[cfg(feature = "experimental-leveldb")]
+        "leveldb",
+    ]
+}
+
 /// A Minetest world
 ///
 /// ```
 /// use minetestworld::World;
+/// # #[cfg(feature = "async-std")]
+/// use async_std::task;
+/// # #[cfg(feature = "tokio")]
+/// # mod task {
+/// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+/// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+/// #     }
+/// # }
 ///
-/// let world = World::open("TestWorld");
+/// let world = task::block_on(World::open("TestWorld")).unwrap();
 /// ```
-pub struct World(PathBuf);
+pub struct World {
+    path: PathBuf,
+    strict: bool,
+    /// The map data handles opened so far, indexed by whether they are read-only
+    map_data: Mutex<[Option<MapData>; 2]>,
+}
 
 impl World {
     /// Creates a new world object from a directory path.
     ///
     /// No further checks are done, e.g. for existence of essential files.
-    pub fn open(path: impl AsRef<Path>) -> Self {
-        World(path.as_ref().to_path_buf())
+    /// Use [`World::open`] to validate the directory.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        World {
+            path: path.as_ref().to_path_buf(),
+            strict: false,
+            map_data: Mutex::new([None, None]),
+        }
+    }
+
+    /// Switches to strict mode, in which missing configuration is an error
+    ///
+    /// By default, a world without `world.mt` or without a `backend` in it is assumed to use
+    /// the SQLite backend, which is logged as a warning.
+    /// In strict mode, [`World::backend`] and the functions that rely on it fail instead.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let world = World::new("NoSuchWorld").strict();
+    /// assert!(task::block_on(world.backend()).is_err());
+    /// ```
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Opens the world located in the given directory.
+    ///
+    /// Fails if the directory does not exist or contains neither a `world.mt`
+    /// nor a recognizable map database.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// task::block_on(async {
+    ///     assert!(World::open("TestWorld").await.is_ok());
+    ///     assert!(World::open("NoSuchWorld").await.is_err());
+    /// });
+    /// ```
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, WorldError> {
+        let path = path.as_ref();
+        let is_dir = fs::metadata(path)
+            .await
+            .map(|m| m.is_dir())
+            .unwrap_or(false);
+        if !is_dir {
+            return Err(WorldError::InvalidWorldDirectory(
+                path.to_path_buf(),
+                String::from("not an existing directory"),
+            ));
+        }
+        for file in ["world.mt", "map.sqlite", "map.db"] {
+            if fs::metadata(path.join(file)).await.is_ok() {
+                return Ok(World::new(path));
+            }
+        }
+        Err(WorldError::InvalidWorldDirectory(
+            path.to_path_buf(),
+            String::from("contains neither world.mt nor a map database"),
+        ))
     }
 
     /// Create a new world from scratch at the given location
     ///
     /// The world will use sqlite as backend.
+    /// The directory is created if needed; if it already exists, it has to be empty.
     #[cfg(feature = "sqlite")]
     pub async fn create_sqlite(path: impl AsRef<Path>) -> Result<World, WorldError> {
         let path = path.as_ref();
-        fs::DirBuilder::new().create(path).await?;
-        fs::write(path.join("world.mt"), "enable_damage = true\ncreative_mode = false\nmod_storage_backend = sqlite3\nauth_backend = sqlite3\nplayer_backend = sqlite3\nbackend = sqlite3\ngameid = minetest\nworld_name = Neue Welt\nserver_announce = false")
-            .await?;
-        Ok(World::open(path))
+        create_empty_dir(path).await?;
+        write_new_world_mt(path, &[("backend", Backend::Sqlite.as_str())]).await?;
+        MapData::from_sqlite_file(path.join("map.sqlite"), false).await?;
+        Ok(World::new(path))
+    }
+
+    /// Create a new world from scratch at the given location, backed by PostgreSQL
+    ///
+    /// `connection_params` is a keyword/value connection string as used for
+    /// `pgsql_connection` in `world.mt`, e.g. `host=localhost user=minetest dbname=world`.
+    /// The `blocks` table is created if it does not exist yet.
+    /// The directory is created if needed; if it already exists, it has to be empty.
+    #[cfg(feature = "postgres")]
+    pub async fn create_postgres(
+        path: impl AsRef<Path>,
+        connection_params: &str,
+    ) -> Result<World, WorldError> {
+        let path = path.as_ref();
+        let uri = keyvalue_to_uri_connectionstr(connection_params)
+            .map_err(WorldError::BogusBackendConfig)?;
+        create_empty_dir(path).await?;
+        MapData::create_postgres_schema(&uri).await?;
+        write_new_world_mt(
+            path,
+            &[
+                ("backend", "postgresql"),
+                ("pgsql_connection", connection_params),
+            ],
+        )
+        .await?;
+        Ok(World::new(path))
+    }
+
+    /// Create a new world from scratch at the given location, backed by Redis
+    ///
+    /// The map data will be stored in the hash `hash` of the Redis instance at
+    /// `address` and, if given, `port`.
+    /// The directory is created if needed; if it already exists, it has to be empty.
+    #[cfg(feature = "redis")]
+    pub async fn create_redis(
+        path: impl AsRef<Path>,
+        address: &str,
+        port: Option<u16>,
+        hash: &str,
+    ) -> Result<World, WorldError> {
+        let path = path.as_ref();
+        create_empty_dir(path).await?;
+        let port = port.map(|p| p.to_string());
+        let mut settings = vec![
+            ("backend", Backend::Redis.as_str()),
+            ("redis_address", address),
+        ];
+        if let Some(port) = &port {
+            settings.push(("redis_port", port));
+        }
+        settings.push(("redis_hash", hash));
+        write_new_world_mt(path, &settings).await?;
+        Ok(World::new(path))
     }
 
     /// Reads the basic metadata of the world.
     ///
     /// ```
     /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
     /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
     ///
     /// let meta = task::block_on(async {
-    ///     World::open("TestWorld").get_world_metadata().await
+    ///     World::open("TestWorld").await.unwrap().get_world_metadata().await
     /// }).unwrap();
     /// assert_eq!(meta.get("world_name").unwrap(), "Hallo");
     /// assert_eq!(meta.get("backend").unwrap(), "sqlite3");
     /// assert_eq!(meta.get("gameid").unwrap(), "minetest");
     /// ```
-    pub async fn get_world_metadata(&self) -> std::io::Result<HashMap<String, String>> {
-        let World(path) = self;
-        let file = File::open(path.join("world.mt")).await?;
-        let reader = BufReader::new(file);
-        let mut result = HashMap::new();
-        let mut lines = reader.lines();
-        while let Some(line) = lines.next().await {
-            if let Some((key, value)) = line?.split_once('=') {
-                result.insert(
-                    String::from(key.trim_end()),
-                    String::from(value.trim_start()),
-                );
-            }
+    pub async fn get_world_metadata(&self) -> std::io::Result<WorldMetadata> {
+        let World { path, .. } = self;
+        Ok(parse_settings(
+            &fs::read_to_string(path.join("world.mt")).await?,
+        ))
+    }
+
+    /// Writes the metadata back into the world's `world.mt`
+    ///
+    /// The settings are written in the order of `meta`, so metadata obtained by
+    /// [`World::get_world_metadata`] keeps its original key order.
+    /// Comments and blank lines of the original file are not preserved.
+    pub async fn set_world_metadata(&self, meta: &WorldMetadata) -> std::io::Result<()> {
+        let World { path, .. } = self;
+        let mut content = String::new();
+        for (key, value) in meta {
+            content.push_str(&format!("{key} = {value}\n"));
         }
-        Ok(result)
+        fs::write(path.join("world.mt"), content).await
     }
 
-    async fn get_backend_name(&self) -> Result<String, WorldError> {
+    /// Returns the map data backend configured in `world.mt`
+    ///
+    /// If there is no `world.mt` or it does not mention a backend,
+    /// [`Backend::Sqlite`] is assumed, like Minetest does.
+    /// In [strict mode](`World::strict`), this is an error instead.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// use minetestworld::world::Backend;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let backend = task::block_on(async {
+    ///     World::open("TestWorld").await.unwrap().backend().await
+    /// }).unwrap();
+    /// assert_eq!(backend, Backend::Sqlite);
+    /// ```
+    pub async fn backend(&self) -> Result<Backend, WorldError> {
         match self.get_world_metadata().await {
             Err(e) => {
-                if e.kind() == std::io::ErrorKind::NotFound {
+                if e.kind() == std::io::ErrorKind::NotFound && !self.strict {
                     log::warn!("No world.mt found, falling back to sqlite3 backend");
-                    Ok(String::from("sqlite3"))
+                    Ok(Backend::Sqlite)
                 } else {
                     Err(WorldError::IOError(e))
                 }
             }
             Ok(metadata) => match metadata.get("backend") {
-                Some(backend) => Ok(backend.clone()),
+                Some(backend) => Ok(Backend::from(backend.as_str())),
+                None if self.strict => Err(WorldError::BogusBackendConfig(String::from(
+                    "world.mt does not name a backend",
+                ))),
                 None => {
                     log::warn!("No backend mentioned in world.mt, falling back to sqlite3");
-                    Ok(String::from("sqlite3"))
+                    Ok(Backend::Sqlite)
                 }
             },
         }
     }
 
+    /// Reads the mapgen parameters from `map_meta.txt`
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let map_meta = task::block_on(async {
+    ///     World::open("TestWorld").await.unwrap().map_meta().await
+    /// }).unwrap();
+    /// assert_eq!(map_meta.mg_name, "v7");
+    /// assert_eq!(map_meta.params.get("chunksize").unwrap(), "5");
+    /// ```
+    pub async fn map_meta(&self) -> Result<MapMeta, WorldError> {
+        let World { path, .. } = self;
+        let params = parse_settings(&fs::read_to_string(path.join("map_meta.txt")).await?);
+        let get = |key: &str| {
+            params.get(key).ok_or_else(|| {
+                WorldError::MapMetaMalformed(format!("'{key}' is missing in map_meta.txt"))
+            })
+        };
+        Ok(MapMeta {
+            seed: get("seed")?.parse()?,
+            mg_name: get("mg_name")?.clone(),
+            water_level: get("water_level")?.parse()?,
+            params,
+        })
+    }
+
+    /// Returns the names of the mods that are enabled in `world.mt`
+    ///
+    /// A mod is enabled by a `load_mod_<name> = true` entry.
+    /// The names are returned in the order of `world.mt`.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let mods = task::block_on(async {
+    ///     World::open("TestWorld").await.unwrap().enabled_mods().await
+    /// }).unwrap();
+    /// assert!(mods.is_empty());
+    /// ```
+    pub async fn enabled_mods(&self) -> Result<Vec<String>, WorldError> {
+        let meta = self.get_world_metadata().await?;
+        Ok(meta
+            .iter()
+            .filter(|(_, value)| value.as_str() == "true")
+            .filter_map(|(key, _)| key.strip_prefix("load_mod_"))
+            .map(String::from)
+            .collect())
+    }
+
+    /// Returns the ID of the game this world is played with, e.g. `minetest`
+    ///
+    /// This is the `gameid` of `world.mt`, or `None` if it does not name one.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let game_id = task::block_on(async {
+    ///     World::open("TestWorld").await.unwrap().game_id().await
+    /// }).unwrap();
+    /// assert_eq!(game_id.as_deref(), Some("minetest"));
+    /// ```
+    pub async fn game_id(&self) -> Result<Option<String>, WorldError> {
+        self.world_mt_value("gameid").await
+    }
+
+    /// Returns the name of this world
+    ///
+    /// This is the `world_name` of `world.mt`, or `None` if it does not name one.
+    pub async fn world_name(&self) -> Result<Option<String>, WorldError> {
+        self.world_mt_value("world_name").await
+    }
+
+    /// Looks up a setting of `world.mt`, trimming surrounding whitespace
+    async fn world_mt_value(&self, key: &str) -> Result<Option<String>, WorldError> {
+        let mut meta = self.get_world_metadata().await?;
+        Ok(meta
+            .swap_remove(key)
+            .map(|value| String::from(value.trim())))
+    }
+
+    /// Reads the data of all players that have joined this world, sorted by name
+    ///
+    /// Depending on the `player_backend` in `world.mt`, the players are read
+    /// from the `players` directory or from `players.sqlite`.
+    /// If neither exists yet, no player has joined and the list is empty.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let players = task::block_on(async {
+    ///     World::open("TestWorld").await.unwrap().players().await
+    /// }).unwrap();
+    /// assert_eq!(players[0].name, "singleplayer");
+    /// ```
+    pub async fn players(&self) -> Result<Vec<Player>, WorldError> {
+        let World { path, .. } = self;
+        let player_backend = match self.get_world_metadata().await {
+            Ok(mut meta) => meta.swap_remove("player_backend"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        match player_backend.as_deref().unwrap_or("files") {
+            "files" => players::read_player_files(&path.join("players")).await,
+            #[cfg(feature = "sqlite")]
+            "sqlite3" => players::read_players_sqlite(&path.join("players.sqlite")).await,
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite3" => Err(WorldError::BackendFeatureDisabled(String::from("sqlite3"))),
+            backend => Err(WorldError::UnknownBackend(String::from(backend))),
+        }
+    }
+
+    /// Reads the authentication data of all players, sorted by name
+    ///
+    /// Only the `sqlite3` auth backend is supported.
+    /// If `auth.sqlite` does not exist yet, no player has joined and the list is empty.
+    /// Like Minetest, the `files` backend is assumed if `world.mt` does not name one.
+    ///
+    /// ```
+    /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
+    /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
+    ///
+    /// let auth = task::block_on(async {
+    ///     World::open("TestWorld").await.unwrap().auth().await
+    /// }).unwrap();
+    /// assert_eq!(auth[1].name, "singleplayer");
+    /// ```
+    #[cfg(feature = "sqlite")]
+    pub async fn auth(&self) -> Result<Vec<AuthRecord>, WorldError> {
+        let World { path, .. } = self;
+        let auth_backend = match self.get_world_metadata().await {
+            Ok(mut meta) => meta.swap_remove("auth_backend"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        match auth_backend.as_deref().unwrap_or("files") {
+            "sqlite3" => auth::read_auth_sqlite(&path.join("auth.sqlite")).await,
+            backend => Err(WorldError::UnsupportedAuthBackend(String::from(backend))),
+        }
+    }
+
     /// Returns a handle to the map database.
     ///
+    /// The handle is opened on the first call and shared by all later calls
+    /// with the same `read_only` value, so its connection pool is reused.
+    /// Closing it closes the pool for all of them.
+    ///
     /// ```
     /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
     /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
     ///
     /// let map_data = task::block_on(async {
-    ///     World::open("TestWorld").get_map_data().await.unwrap()
+    ///     World::open("TestWorld").await.unwrap().get_map_data().await.unwrap()
     /// });
     /// ```
     pub async fn get_map_data_backend(&self, read_only: bool) -> Result<MapData, WorldError> {
-        let backend = self.get_backend_name().await?;
-        match backend.as_str() {
+        let mut opened = self.map_data.lock().await;
+        let slot = &mut opened[read_only as usize];
+        if let Some(map_data) = slot {
+            return Ok(map_data.clone());
+        }
+        // Boxed, as the backend futures are large
+        let map_data = Box::pin(self.open_map_data_backend(read_only)).await?;
+        *slot = Some(map_data.clone());
+        Ok(map_data)
+    }
+
+    async fn open_map_data_backend(&self, read_only: bool) -> Result<MapData, WorldError> {
+        match self.backend().await? {
             #[cfg(feature = "sqlite")]
-            "sqlite3" => {
-                let World(path) = self;
+            Backend::Sqlite => {
+                let World { path, .. } = self;
                 Ok(MapData::from_sqlite_file(path.join("map.sqlite"), read_only).await?)
             }
             #[cfg(feature = "postgres")]
-            "postgresql" => {
+            Backend::Postgres => {
                 let meta = self.get_world_metadata().await?;
                 let connstr = meta.get("pgsql_connection").ok_or_else(|| {
                     WorldError::BogusBackendConfig(String::from(
@@ -123,7 +602,7 @@ impl World {
                 Ok(MapData::from_pg_connection_params(uri).await?)
             }
             #[cfg(feature = "redis")]
-            "redis" => {
+            Backend::Redis => {
                 let meta = self.get_world_metadata().await?;
                 let host = meta.get("redis_address").ok_or_else(|| {
                     WorldError::BogusBackendConfig(String::from(
@@ -140,15 +619,17 @@ impl World {
                 Ok(MapData::from_redis_connection_params(host, port, hash).await?)
             }
             #[cfg(feature = "experimental-leveldb")]
-            "leveldb" => {
-                let World(path) = self;
+            Backend::LevelDb => {
+                let World { path, .. } = self;
                 let path = path.clone();
-                Ok(
-                    task::spawn_blocking(move || MapData::from_leveldb(path.join("map.db")))
-                        .await?,
-                )
+                Ok(crate::runtime::spawn_blocking(move || {
+                    MapData::from_leveldb(path.join("map.db"))
+                })
+                .await?)
             }
-            _ => Err(WorldError::UnknownBackend(backend)),
+            Backend::Unknown(backend) => Err(WorldError::UnknownBackend(backend)),
+            #[allow(unreachable_patterns)]
+            backend => Err(WorldError::BackendFeatureDisabled(backend.to_string())),
         }
     }
 
@@ -158,10 +639,17 @@ impl World {
     ///
     /// ```
     /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
     /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
     ///
     /// let map_data = task::block_on(async {
-    ///     World::open("TestWorld").get_map_data().await.unwrap()
+    ///     World::open("TestWorld").await.unwrap().get_map_data().await.unwrap()
     /// });
     /// ```
     pub async fn get_map_data(&self) -> Result<MapData, WorldError> {
@@ -174,10 +662,17 @@ impl World {
     ///
     /// ```ignore
     /// use minetestworld::World;
+    /// # #[cfg(feature = "async-std")]
     /// use async_std::task;
+    /// # #[cfg(feature = "tokio")]
+    /// # mod task {
+    /// #     pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// #         tokio::runtime::Runtime::new().unwrap().block_on(future)
+    /// #     }
+    /// # }
     ///
     /// let map_data = task::block_on(async {
-    ///     World::open("TestWorld").get_mutable_map_data().await.unwrap()
+    ///     World::open("TestWorld").await.unwrap().get_mutable_map_data().await.unwrap()
     /// });
     /// ```
     pub async fn get_mutable_map_data(&self) -> Result<MapData, WorldError> {
@@ -188,6 +683,129 @@ impl World {
     pub async fn get_voxel_manip(&self, writable: bool) -> Result<VoxelManip, WorldError> {
         Ok(VoxelManip::new(self.get_map_data_backend(!writable).await?))
     }
+
+    /// Snapshots the map into the directory `dest`
+    ///
+    /// The map database is copied as `map.sqlite` using `VACUUM INTO`, which yields a consistent
+    /// copy even while Minetest is running. `world.mt` is copied along, if present, so that the
+    /// backup can be opened as a world. Player and auth data are not included.
+    ///
+    /// `dest` is created if it does not exist. If it already contains a non-empty `map.sqlite`,
+    /// this fails with [`std::io::ErrorKind::AlreadyExists`].
+    /// Only the SQLite backend is supported; other backends fail with
+    /// [`WorldError::UnsupportedBackup`] without connecting to the map database.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    pub async fn backup(&self, dest: impl AsRef<Path>) -> Result<(), WorldError> {
+        match self.backend().await? {
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => match self.get_map_data().await? {
+                MapData::Sqlite(pool) => self.backup_sqlite(&pool, dest.as_ref()).await,
+                #[allow(unreachable_patterns)]
+                _ => Err(WorldError::UnsupportedBackup(Backend::Sqlite.to_string())),
+            },
+            backend => Err(WorldError::UnsupportedBackup(backend.to_string())),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn backup_sqlite(&self, pool: &sqlx::SqlitePool, dest: &Path) -> Result<(), WorldError> {
+        let map_file = dest.join("map.sqlite");
+        let map_file = map_file.to_str().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("The backup path {map_file:?} is not valid UTF-8"),
+            )
+        })?;
+        fs::create_dir_all(dest).await?;
+        sqlx::query("VACUUM INTO ?")
+            .bind(map_file)
+            .execute(pool)
+            .await
+            .map_err(|e| match e {
+                // SQLite refuses to overwrite a database that is not empty
+                sqlx::Error::Database(e) if e.message().contains("output file already exists") => {
+                    WorldError::IOError(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("The backup {map_file:?} already exists"),
+                    ))
+                }
+                e => MapDataError::SqlError(e).into(),
+            })?;
+        match fs::read(self.path.join("world.mt")).await {
+            Ok(world_mt) => fs::write(dest.join("world.mt"), world_mt).await?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+}
+
+/// Parses `key = value` lines as used by `world.mt` and `map_meta.txt`
+///
+/// Parsing stops at an `[end_of_params]` line. Groups spanning multiple lines
+/// (from `key = {` up to `}`) are kept verbatim as value.
+/// Lines starting with `#` are comments. Keys and values are trimmed,
+/// which also removes the `\r` of CRLF line endings.
+pub(crate) fn parse_settings(text: &str) -> WorldMetadata {
+    let mut result = WorldMetadata::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line == "[end_of_params]" {
+            break;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let mut value = String::from(value.trim());
+            if value == "{" {
+                for group_line in lines.by_ref() {
+                    let group_line = group_line.trim_end();
+                    value.push('\n');
+                    value.push_str(group_line);
+                    if group_line.trim_start() == "}" {
+                        break;
+                    }
+                }
+            }
+            result.insert(String::from(key.trim()), value);
+        }
+    }
+    result
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "redis"))]
+/// Creates the directory at `path`, unless it already exists and is empty
+async fn create_empty_dir(path: &Path) -> Result<(), WorldError> {
+    match fs::read_dir(path).await {
+        Ok(entries) if entries.is_empty() => Ok(()),
+        Ok(_) => Err(WorldError::InvalidWorldDirectory(
+            path.to_path_buf(),
+            String::from("directory exists and is not empty"),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(fs::create_dir_all(path).await?),
+        Err(e) => Err(WorldError::IOError(e)),
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "redis"))]
+/// Writes a minimal `world.mt` for a new world, including the given backend settings
+async fn write_new_world_mt(path: &Path, backend_settings: &[(&str, &str)]) -> std::io::Result<()> {
+    let world_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let mut meta = WorldMetadata::new();
+    meta.insert(String::from("gameid"), String::from("minetest"));
+    meta.insert(String::from("world_name"), world_name.into_owned());
+    for (key, value) in backend_settings {
+        meta.insert(key.to_string(), value.to_string());
+    }
+    for key in ["auth_backend", "player_backend", "mod_storage_backend"] {
+        meta.insert(String::from(key), String::from("sqlite3"));
+    }
+    World::new(path).set_world_metadata(&meta).await
 }
 
 /// Represents a failure to interact with the world
@@ -199,9 +817,33 @@ pub enum WorldError {
     #[error("Map data error: {0}")]
     /// The map data backend returned an error
     MapDataError(#[from] MapDataError),
+    #[error("Invalid world directory {0:?}: {1}")]
+    /// The given path does not point to a world directory
+    ///
+    /// A description of the problem is included.
+    InvalidWorldDirectory(PathBuf, String),
     #[error("Unknown backend '{0}'")]
     /// The map data backend is not known or implemented
     UnknownBackend(String),
+    #[error("The '{0}' backend requires the `{}` feature, which is disabled", backend_feature(.0))]
+    /// The backend is known, but the crate feature that implements it is disabled
+    BackendFeatureDisabled(String),
+    #[error("Backing up the '{0}' backend is not supported")]
+    /// [`World::backup`] does not support the map data backend, only `sqlite3`
+    UnsupportedBackup(String),
+    #[error("Reading the '{0}' auth backend is not supported")]
+    /// The auth backend is not implemented, only `sqlite3` is
+    UnsupportedAuthBackend(String),
+    #[error("Malformed player data: {0}")]
+    /// The player data could not be read
+    ///
+    /// A description is included.
+    PlayerDataMalformed(String),
+    #[error("Malformed map_meta.txt: {0}")]
+    /// The mapgen parameters could not be read
+    ///
+    /// A description is included.
+    MapMetaMalformed(String),
     #[error("Bogus backend config: {0}")]
     /// The map data backend config contains an error
     ///
@@ -216,6 +858,17 @@ pub enum WorldError {
     ParseIntError(#[from] std::num::ParseIntError),
 }
 
+/// Returns the name of the crate feature that implements a backend
+fn backend_feature(backend: &str) -> &str {
+    match Backend::from(backend) {
+        Backend::Sqlite => "sqlite",
+        Backend::Postgres => "postgres",
+        Backend::Redis => "redis",
+        Backend::LevelDb => "experimental-leveldb",
+        Backend::Unknown(_) => backend,
+    }
+}
+
 /// Converts a postgres connection string from keyvalue to URI
 #[cfg(feature = "postgres")]
 pub(crate) fn keyvalue_to_uri_connectionstr(
@@ -0,0 +1,39 @@
+#![cfg(all(feature = "blocking", feature = "sqlite"))]
+use std::error::Error;
+mod common;
+use minetestworld::blocking::World;
+use minetestworld::Position;
+
+#[test]
+fn read_mapblock() -> Result<(), Box<dyn Error>> {
+    let world = World::open("TestWorld")?;
+    let data = world.get_map_data()?;
+    assert_eq!(data.all_mapblock_positions()?.len(), 5923);
+    let block = data.get_mapblock(Position::new(-13i16, -8, 2))?;
+    assert_eq!(block.map_format_version, 29);
+    Ok(())
+}
+
+fn change_voxel() -> Result<(), Box<dyn Error>> {
+    let world = World::open("TestWorld copy")?;
+    let mut vm = world.get_voxel_manip(true)?;
+    vm.set_content(Position::new(0i16, 0, 0), b"default:diamond")?;
+    vm.commit()?;
+    std::mem::drop(vm);
+
+    let mut vm = world.get_voxel_manip(true)?;
+    let node = vm.get_node(Position::new(0i16, 0, 0))?;
+    assert_eq!(node.param0, b"default:diamond");
+    Ok(())
+}
+
+#[test]
+fn test_change() -> Result<(), Box<dyn Error>> {
+    async_std::task::block_on(common::tear_up())?;
+    // No early return here, so that tear down happens in every case
+    let result = change_voxel();
+    let cleanup_result = async_std::task::block_on(common::tear_down());
+    result?;
+    cleanup_result?;
+    Ok(())
+}
@@ -1,3 +1,4 @@
+#![cfg(feature = "sqlite")]
 use std::error::Error;
 mod common;
 use futures::TryStreamExt;
@@ -5,7 +6,7 @@ use minetestworld::World;
 
 /// Reading and writing a block should be more-or-less a no-op
 async fn nop() -> Result<(), Box<dyn Error>> {
-    let world = World::open("TestWorld copy");
+    let world = World::open("TestWorld copy").await?;
     let data = world.get_map_data_backend(false).await?;
     let positions: Vec<_> = data.all_mapblock_positions().await.try_collect().await?;
     for pos in positions {
@@ -27,7 +28,8 @@ async fn nop() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-#[async_std::test]
+#[cfg_attr(feature = "async-std", async_std::test)]
+#[cfg_attr(feature = "tokio", tokio::test)]
 async fn test_nop() -> Result<(), Box<dyn Error>> {
     common::tear_up().await?;
     // No early return here, so that tear down happens in every case
@@ -1,9 +1,10 @@
+#![cfg(feature = "sqlite")]
 use std::error::Error;
 mod common;
 use minetestworld::{Position, World};
 
 async fn change_voxel() -> Result<(), minetestworld::world::WorldError> {
-    let world = World::open("TestWorld copy");
+    let world = World::open("TestWorld copy").await?;
     let mut vm = world.get_voxel_manip(true).await?;
     vm.set_content(Position::new(0i16, 0, 0), b"default:diamond")
         .await?;
@@ -16,7 +17,8 @@ async fn change_voxel() -> Result<(), minetestworld::world::WorldError> {
     Ok(())
 }
 
-#[async_std::test]
+#[cfg_attr(feature = "async-std", async_std::test)]
+#[cfg_attr(feature = "tokio", tokio::test)]
 async fn test_change() -> Result<(), Box<dyn Error>> {
     common::tear_up().await?;
     // No early return here, so that tear down happens in every case
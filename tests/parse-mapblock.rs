@@ -0,0 +1,12 @@
+//! Only uses the parts of the crate that are available without any backend feature
+use minetestworld::{MapBlock, Position};
+
+#[test]
+fn parse_standalone_mapblock() {
+    let data = std::fs::read("TestWorld/testmapblock").unwrap();
+    let mapblock = MapBlock::from_bytes(&data).unwrap();
+    assert_eq!(mapblock.map_format_version, 29);
+    let (blockpos, _) = Position::new(-200i16, 5, 17).split_at_block();
+    assert_eq!(blockpos, Position::new(-13i16, 0, 1));
+    assert!(!mapblock.name_id_mappings.is_empty());
+}